@@ -0,0 +1,194 @@
+//! 임시 인덱스 파일 수백 개를 fan-in을 바꿔가며 병합하는 시간을 잰다.
+//! 힙으로 다음 단어를 고르므로 fan-in을 늘려도 단어마다 드는 비용은 log(fan-in)만큼만 는다.
+//! 비교를 위해 같은 파일들을 한 번에 병합하는 시간을 힙과 예전의 최솟값 훑기로 각각 잰다.
+//!
+//! cargo bench --bench merge -- [파일 수]
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use fingertips::{
+    analysis::Pipeline,
+    error::{Error, PathContext},
+    index::{split_hits, InMemoryIndex, PostingListBuilder},
+    merge::{merge_stream, FileMerge},
+    progress::NoProgress,
+    read::IndexFileReader,
+    tmp::TmpDir,
+    tombstone::Tombstones,
+    write::{write_index_to_tmp_file, IndexFileWriter},
+};
+
+/// 임시 파일 하나에 넣을 문서 수
+const DOCUMENTS_PER_FILE: usize = 10;
+/// 문서 하나의 단어 수
+const WORDS_PER_DOCUMENT: usize = 200;
+/// 단어의 종류
+const VOCABULARY: u64 = 50_000;
+
+fn main() -> Result<(), Error> {
+    let nfiles = env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(300);
+    let dir = env::temp_dir().join(format!("fingertips-bench-{}", std::process::id()));
+    let analyzer = Pipeline::default();
+
+    let results = [2, 8, 32, 128, nfiles]
+        .into_iter()
+        .map(|fan_in| {
+            fs::create_dir_all(&dir)?;
+            let tmp_dir = TmpDir::new(&dir);
+            let files = write_files(nfiles, &analyzer, tmp_dir.clone())?;
+
+            let start = Instant::now();
+            let mut merge = FileMerge::new(
+                &dir.join("index.dat"),
+                tmp_dir,
+                fan_in,
+                Arc::new(NoProgress),
+            );
+            files
+                .into_iter()
+                .try_for_each(|file| merge.add_file(file))?;
+            merge.finish()?;
+            let elapsed = start.elapsed();
+
+            fs::remove_dir_all(&dir)?;
+            Ok((fan_in, elapsed))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let passes = [2, 8, 32, 128, nfiles]
+        .into_iter()
+        .map(|nstreams| {
+            let heap = time_one_pass(&dir, nstreams, &analyzer, |files, out_path, out| {
+                merge_stream(files, out_path, out, &Tombstones::new(), None, None)
+            })?;
+            let min_scan = time_one_pass(&dir, nstreams, &analyzer, merge_min_scan)?;
+            Ok((nstreams, heap, min_scan))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    println!("merged {} files", nfiles);
+    results
+        .iter()
+        .for_each(|(fan_in, elapsed)| println!("fan-in {:>4}: {:?}", fan_in, elapsed));
+    println!("one pass");
+    passes.iter().for_each(|(nstreams, heap, min_scan)| {
+        println!(
+            "{:>4} files: heap {:?}, min-scan {:?}",
+            nstreams, heap, min_scan
+        )
+    });
+    Ok(())
+}
+
+/// 임시 파일 nfiles개를 merge로 한 번에 병합하는 시간
+/// 파일은 매번 같은 내용으로 새로 만든다. 병합하면 읽은 파일은 지워진다.
+fn time_one_pass<F>(
+    dir: &Path,
+    nfiles: usize,
+    analyzer: &Pipeline,
+    merge: F,
+) -> Result<Duration, Error>
+where
+    F: FnOnce(Vec<PathBuf>, &Path, BufWriter<File>) -> Result<u64, Error>,
+{
+    fs::create_dir_all(dir)?;
+    let mut tmp_dir = TmpDir::new(dir);
+    let files = write_files(nfiles, analyzer, tmp_dir.clone())?;
+    let (out_path, out) = tmp_dir.create()?;
+
+    let start = Instant::now();
+    merge(files, &out_path, out)?;
+    let elapsed = start.elapsed();
+
+    fs::remove_dir_all(dir)?;
+    Ok(elapsed)
+}
+
+/// 힙을 쓰기 전의 병합. 단어마다 모든 파일의 다음 단어를 훑어서 가장 작은 단어를 고른다.
+/// `merge::merge_stream`과 같은 파일을 만들고 다음 단어를 고르는 방법만 다르다.
+fn merge_min_scan(
+    files: Vec<PathBuf>,
+    out_path: &Path,
+    out: BufWriter<File>,
+) -> Result<u64, Error> {
+    let mut streams = files
+        .into_iter()
+        .map(IndexFileReader::open)
+        .collect::<Result<Vec<_>, _>>()?;
+    let analyzer = streams.first().map_or("", |s| s.analyzer()).to_string();
+    let mut output = IndexFileWriter::new(out, &analyzer).with_path(out_path)?;
+
+    while let Some(term) = streams
+        .iter()
+        .filter_map(|s| s.peek())
+        .map(|entry| &entry.term)
+        .min()
+        .cloned()
+    {
+        let buffers = streams
+            .iter_mut()
+            .filter(|s| s.is_at(&term))
+            .map(|s| Ok((s.filename().to_path_buf(), s.take_hits()?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut hits = buffers
+            .iter()
+            .map(|(filename, buf)| split_hits(buf).with_path(filename))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        hits.sort_by_key(|hit| (hit.document_id, hit.field));
+
+        let mut postings = PostingListBuilder::new();
+        hits.into_iter().try_for_each(|hit| postings.push(hit))?;
+        let df = postings.df();
+        let point = output.offset();
+        output
+            .write_main(&postings.into_bytes())
+            .with_path(out_path)?;
+        output.write_content_entry(term, df, point, output.offset() - point);
+    }
+
+    output.finish().with_path(out_path)
+}
+
+/// 무작위 단어로 된 문서를 색인해서 임시 파일 nfiles개를 만든다.
+fn write_files(
+    nfiles: usize,
+    analyzer: &Pipeline,
+    mut tmp_dir: TmpDir,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next_word = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        format!("w{}", seed % VOCABULARY)
+    };
+
+    (0..nfiles)
+        .map(|file| {
+            let index = (0..DOCUMENTS_PER_FILE).fold(InMemoryIndex::new(), |mut index, i| {
+                let text = (0..WORDS_PER_DOCUMENT)
+                    .map(|_| next_word())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let document_id = file * DOCUMENTS_PER_FILE + i;
+                index.merge(InMemoryIndex::from_single_document(
+                    document_id,
+                    text,
+                    analyzer,
+                ));
+                index
+            });
+            write_index_to_tmp_file(index, &mut tmp_dir, analyzer.spec(), &NoProgress)
+        })
+        .collect()
+}
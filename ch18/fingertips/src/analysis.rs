@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+
+use rust_stemmers::{Algorithm, Stemmer};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::Error;
+
+/// 분석기를 지정하지 않았을 때 쓰는 설정
+pub const DEFAULT_ANALYZER: &str = "lowercase,cjk";
+
+/// 분석한 토큰
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    /// 문서 안에서의 위치(토큰 번호)
+    pub position: u32,
+    /// 원문에서 토큰이 시작하는 바이트 위치
+    pub start: usize,
+    /// 원문에서 토큰이 끝나는 바이트 위치
+    pub end: usize,
+}
+
+/// 텍스트를 색인할 토큰으로 나눈다.
+pub trait Analyzer: Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<Token>;
+}
+
+/// 토큰 목록을 바꾸는 필터
+/// 토큰을 빼더라도 남은 토큰의 위치는 바꾸지 않는다. 구절 검색이 빈 자리를 알 수 있어야 한다.
+pub trait TokenFilter: Send + Sync {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token>;
+
+    /// 접두어나 와일드카드 패턴에 적용할 글자 단위의 변환
+    /// 토큰을 나누거나 빼거나 바꿔 쓰는 필터는 패턴을 그대로 둔다.
+    fn normalize(&self, text: String) -> String {
+        text
+    }
+}
+
+/// 영숫자가 아닌 문자에서 텍스트를 나눈다.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut push = |start: usize, end: usize| {
+        tokens.push(Token {
+            text: text[start..end].to_string(),
+            position: tokens.len() as u32,
+            start,
+            end,
+        })
+    };
+    text.char_indices()
+        .for_each(|(i, ch)| match (ch.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                push(s, i);
+                start = None;
+            }
+            _ => {}
+        });
+    if let Some(s) = start {
+        push(s, text.len());
+    }
+    tokens
+}
+
+/// 소문자로 바꾼다.
+pub struct Lowercase;
+
+impl TokenFilter for Lowercase {
+    fn filter(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .iter_mut()
+            .for_each(|token| token.text = token.text.to_lowercase());
+        tokens
+    }
+
+    fn normalize(&self, text: String) -> String {
+        text.to_lowercase()
+    }
+}
+
+/// 유니코드 NFKC로 정규화한다.
+pub struct Normalize;
+
+impl TokenFilter for Normalize {
+    fn filter(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .iter_mut()
+            .for_each(|token| token.text = token.text.nfkc().collect());
+        tokens
+    }
+
+    fn normalize(&self, text: String) -> String {
+        text.nfkc().collect()
+    }
+}
+
+/// 흔한 영어 단어를 뺀다.
+pub struct StopWords {
+    words: HashSet<&'static str>,
+}
+
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+impl Default for StopWords {
+    fn default() -> Self {
+        StopWords {
+            words: ENGLISH_STOP_WORDS.iter().copied().collect(),
+        }
+    }
+}
+
+impl TokenFilter for StopWords {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .filter(|token| !self.words.contains(token.text.as_str()))
+            .collect()
+    }
+}
+
+/// 영어 어간을 추출한다.
+pub struct Stem {
+    stemmer: Stemmer,
+}
+
+impl Default for Stem {
+    fn default() -> Self {
+        Stem {
+            stemmer: Stemmer::create(Algorithm::English),
+        }
+    }
+}
+
+impl TokenFilter for Stem {
+    fn filter(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .iter_mut()
+            .for_each(|token| token.text = self.stemmer.stem(&token.text).into_owned());
+        tokens
+    }
+}
+
+/// 한중일 문자인지 확인한다.
+fn is_cjk(ch: char) -> bool {
+    matches!(ch,
+        '\u{1100}'..='\u{11FF}' // 한글 자모
+        | '\u{3040}'..='\u{30FF}' // 히라가나, 가타카나
+        | '\u{3130}'..='\u{318F}' // 한글 호환 자모
+        | '\u{3400}'..='\u{4DBF}' // 한자 확장 A
+        | '\u{4E00}'..='\u{9FFF}' // 한자
+        | '\u{AC00}'..='\u{D7A3}' // 한글 음절
+        | '\u{F900}'..='\u{FAFF}' // 한자 호환
+        | '\u{FF66}'..='\u{FF9F}' // 반각 가타카나
+    )
+}
+
+/// 한중일 문자열을 두 글자씩 겹쳐서 나눈다.
+/// 한국어는 어절이 통째로 토큰 하나가 되어서 어절 안의 단어를 찾을 수 없다.
+/// "자료구조를"은 자료, 료구, 구조, 조를 로 나누고, "Rust는"처럼 문자 종류가 바뀌는 곳에서도 나눈다.
+/// 나눈 조각은 각자 위치를 가지므로 뒤 토큰의 위치는 그만큼 밀린다. ngram보다 앞에 써야 한다.
+pub struct CjkBigram;
+
+impl TokenFilter for CjkBigram {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut shift = 0;
+        tokens
+            .into_iter()
+            .flat_map(|token| {
+                let position = token.position + shift;
+                let pieces = segment(token);
+                shift += pieces.len() as u32 - 1;
+                pieces.into_iter().enumerate().map(move |(i, piece)| Token {
+                    position: position + i as u32,
+                    ..piece
+                })
+            })
+            .collect()
+    }
+}
+
+/// 토큰을 문자 종류가 바뀌는 곳에서 나누고 한중일 문자열은 다시 두 글자씩 나눈다.
+fn segment(token: Token) -> Vec<Token> {
+    if !token.text.chars().any(is_cjk) {
+        return vec![token];
+    }
+    // 앞의 필터가 글자 수를 바꿨으면 원문의 위치를 알 수 없으므로 토큰 전체의 위치를 쓴다.
+    let aligned = token.text.len() == token.end - token.start;
+    let piece = |start: usize, end: usize| Token {
+        text: token.text[start..end].to_string(),
+        position: token.position,
+        start: if aligned {
+            token.start + start
+        } else {
+            token.start
+        },
+        end: if aligned {
+            token.start + end
+        } else {
+            token.end
+        },
+    };
+    let end_of = |(i, ch): (usize, char)| i + ch.len_utf8();
+
+    let chars = token.text.char_indices().collect::<Vec<_>>();
+    chars
+        .chunk_by(|a, b| is_cjk(a.1) == is_cjk(b.1))
+        .flat_map(|run| {
+            let last = run[run.len() - 1];
+            if run.len() > 1 && is_cjk(last.1) {
+                run.windows(2)
+                    .map(|pair| piece(pair[0].0, end_of(pair[1])))
+                    .collect()
+            } else {
+                vec![piece(run[0].0, end_of(last))]
+            }
+        })
+        .collect()
+}
+
+/// 토큰을 글자 n-gram으로 나눈다.
+/// n-gram은 원래 토큰의 위치를 그대로 쓰고, min보다 짧은 토큰은 그대로 둔다.
+pub struct NGram {
+    min: usize,
+    max: usize,
+}
+
+impl TokenFilter for NGram {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .flat_map(|token| {
+                let chars = token.text.chars().collect::<Vec<_>>();
+                if chars.len() <= self.min {
+                    return vec![token];
+                }
+                (self.min..=self.max.min(chars.len()))
+                    .flat_map(|n| chars.windows(n).map(|gram| gram.iter().collect::<String>()))
+                    .map(|text| Token { text, ..token })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// 토크나이저 뒤에 필터를 차례로 이어 붙인 분석기
+/// "lowercase,stop,stem" 같은 설정 문자열로 만들고, 정규화한 설정 문자열을 인덱스 헤더에 기록한다.
+pub struct Pipeline {
+    spec: String,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl Pipeline {
+    /// 설정 문자열로 분석기를 만든다.
+    /// lowercase, nfkc, cjk, stop, stem, ngram:N, ngram:MIN-MAX 를 쉼표로 이어서 쓴다.
+    pub fn from_spec(spec: &str) -> Result<Pipeline, Error> {
+        let (names, filters): (Vec<_>, Vec<_>) = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(parse_filter)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        Ok(Pipeline {
+            spec: names.join(","),
+            filters,
+        })
+    }
+
+    /// 분석기를 만든 설정 문자열
+    /// 공백과 빈 항목을 빼고 ngram:2-2는 ngram:2로 쓰는 식으로 정규화하므로, 같은 분석기는 같은 문자열이다.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// 패턴을 나누지 않고 대소문자와 유니코드 정규화만 적용한다.
+    pub fn normalize(&self, pattern: &str) -> String {
+        self.filters
+            .iter()
+            .fold(pattern.to_string(), |text, filter| filter.normalize(text))
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::from_spec(DEFAULT_ANALYZER).unwrap()
+    }
+}
+
+impl Analyzer for Pipeline {
+    fn analyze(&self, text: &str) -> Vec<Token> {
+        self.filters
+            .iter()
+            .fold(tokenize(text), |tokens, filter| filter.filter(tokens))
+    }
+}
+
+/// 필터와 정규화한 이름
+fn parse_filter(name: &str) -> Result<(String, Box<dyn TokenFilter>), Error> {
+    let bad_spec = || Error::InvalidArgument(format!("unknown analyzer filter: {}", name));
+    let filter: Box<dyn TokenFilter> = match name {
+        "lowercase" => Box::new(Lowercase),
+        "nfkc" => Box::new(Normalize),
+        "cjk" => Box::new(CjkBigram),
+        "stop" => Box::new(StopWords::default()),
+        "stem" => Box::new(Stem::default()),
+        ngram if ngram.starts_with("ngram:") => {
+            let range = &ngram["ngram:".len()..];
+            let (min, max) = match range.split_once('-') {
+                Some((min, max)) => (min.parse(), max.parse()),
+                None => (range.parse(), range.parse()),
+            };
+            match (min, max) {
+                (Ok(min), Ok(max)) if 0 < min && min <= max => {
+                    let name = if min == max {
+                        format!("ngram:{}", min)
+                    } else {
+                        format!("ngram:{}-{}", min, max)
+                    };
+                    return Ok((name, Box::new(NGram { min, max })));
+                }
+                _ => return Err(bad_spec()),
+            }
+        }
+        _ => return Err(bad_spec()),
+    };
+    Ok((name.to_string(), filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<(&str, u32)> {
+        tokens
+            .iter()
+            .map(|token| (token.text.as_str(), token.position))
+            .collect()
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let text = "The Gap-Buffers are running";
+        let tokens = Pipeline::default().analyze(text);
+        assert_eq!(
+            texts(&tokens),
+            [
+                ("the", 0),
+                ("gap", 1),
+                ("buffers", 2),
+                ("are", 3),
+                ("running", 4)
+            ]
+        );
+        assert_eq!(&text[tokens[2].start..tokens[2].end], "Buffers");
+
+        let tokens = Pipeline::from_spec("lowercase,stop,stem")
+            .unwrap()
+            .analyze(text);
+        assert_eq!(texts(&tokens), [("gap", 1), ("buffer", 2), ("run", 4)]);
+
+        let tokens = Pipeline::from_spec("lowercase,ngram:3")
+            .unwrap()
+            .analyze("rust go");
+        assert_eq!(texts(&tokens), [("rus", 0), ("ust", 0), ("go", 1)]);
+
+        assert!(Pipeline::from_spec("lowercase,shout").is_err());
+        assert!(Pipeline::from_spec("ngram:3-2").is_err());
+
+        let spec = |spec| Pipeline::from_spec(spec).unwrap().spec().to_string();
+        assert_eq!(
+            spec(" lowercase , ngram:2-2,,stem "),
+            "lowercase,ngram:2,stem"
+        );
+        assert_eq!(spec("ngram:1-3"), "ngram:1-3");
+        assert_eq!(spec(DEFAULT_ANALYZER), DEFAULT_ANALYZER);
+    }
+
+    #[test]
+    fn test_cjk_bigram() {
+        let text = "Rust는 자료구조를 쓴다";
+        let tokens = Pipeline::default().analyze(text);
+        assert_eq!(
+            texts(&tokens),
+            [
+                ("rust", 0),
+                ("는", 1),
+                ("자료", 2),
+                ("료구", 3),
+                ("구조", 4),
+                ("조를", 5),
+                ("쓴다", 6)
+            ]
+        );
+        assert_eq!(&text[tokens[4].start..tokens[4].end], "구조");
+
+        let tokens = Pipeline::default().analyze("東京タワー");
+        assert_eq!(
+            texts(&tokens),
+            [("東京", 0), ("京タ", 1), ("タワ", 2), ("ワー", 3)]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fmt, path::Path, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use fingertips::{
+    error::Error,
+    parse_args::parse_serve_args,
+    server::{ReloadableIndex, SearchParams},
+};
+use tokio::net::TcpListener;
+
+/// 오류를 {"error": 메시지} 로 돌려준다.
+fn error_response<E: fmt::Display>(status: StatusCode, e: E) -> Response {
+    (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response()
+}
+
+async fn get_search(
+    State(index): State<Arc<ReloadableIndex>>,
+    Query(params): Query<SearchParams>,
+) -> Response {
+    let loaded = index.current();
+    // 검색은 파일을 읽으므로 블로킹 스레드에서 실행한다.
+    let result = tokio::task::spawn_blocking(move || loaded.search(&params)).await;
+    match result {
+        Ok(Ok(response)) => Json(response).into_response(),
+        Ok(Err(e @ Error::InvalidQuery(_))) => error_response(StatusCode::BAD_REQUEST, e),
+        Ok(Err(e)) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// interval마다 인덱스 파일을 확인해서 바뀌었으면 다시 읽는다.
+async fn reload_periodically(index: Arc<ReloadableIndex>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let index = index.clone();
+        match tokio::task::spawn_blocking(move || index.reload_if_changed()).await {
+            Ok(Ok(true)) => println!("reloaded index"),
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => eprintln!("warning: cannot reload index: {}", e),
+            Err(e) => eprintln!("warning: cannot reload index: {}", e),
+        }
+    }
+}
+
+fn main() {
+    let args = parse_serve_args();
+    let index_path = Path::new(args.dir()).join(args.name());
+    let index = match ReloadableIndex::open(&index_path) {
+        Ok(index) => Arc::new(index),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    let app = Router::new()
+        .route("/search", get(get_search))
+        .with_state(index.clone());
+    let task = async {
+        if args.reload_interval() > 0 {
+            tokio::spawn(reload_periodically(
+                index,
+                Duration::from_secs(args.reload_interval()),
+            ));
+        }
+        println!(
+            "Serving {} on http://{}...",
+            index_path.display(),
+            args.addr()
+        );
+        axum::serve(
+            TcpListener::bind(args.addr())
+                .await
+                .expect("error binding server to address."),
+            app,
+        )
+        .await
+        .unwrap();
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(task);
+}
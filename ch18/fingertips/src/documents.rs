@@ -0,0 +1,262 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    error::{Error, PathContext},
+    header::FormatError,
+};
+
+/// 문서 테이블 파일의 확장자. index.dat의 문서 테이블은 index.docs이다.
+pub const DOCUMENTS_EXTENSION: &str = "docs";
+
+/// 문서 테이블 엔트리에서 경로 앞의 길이가 정해진 부분
+/// [id: 4B][len: 8B][word_count: 8B][mtime: 8B][record: 4B][offset: 8B][path_len: 4B]
+const ENTRY_SIZE: u64 = 4 + 8 + 8 + 8 + 4 + 8 + 4;
+
+/// 인덱스 파일의 문서 테이블 경로
+pub fn documents_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension(DOCUMENTS_EXTENSION)
+}
+
+/// 색인한 문서 하나의 정보
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    /// 인덱스에 기록된 문서 번호
+    pub id: u32,
+    pub path: PathBuf,
+    /// JSON-lines 파일에서 레코드가 있는 줄(1부터). 파일 전체가 문서 하나이면 None
+    pub record: Option<u32>,
+    /// 레코드가 시작하는 바이트 위치. `extract::Record::offset` 참고
+    pub offset: u64,
+    /// 파일의 바이트 수. 레코드는 그 레코드가 있는 파일의 바이트 수이다.
+    pub len: u64,
+    /// 문서의 단어 수
+    pub word_count: u64,
+    /// 수정 시각(UNIX 시간, 초)
+    pub mtime: u64,
+}
+
+impl Document {
+    /// 출력할 때 쓰는 이름. 레코드는 `경로:줄`이다.
+    pub fn name(&self) -> String {
+        match self.record {
+            Some(line) => format!("{}:{}", self.path.display(), line),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// 문서 번호 → 경로 테이블
+/// 문서는 번호 순으로 저장한다.
+#[derive(Debug, Default)]
+pub struct DocumentTable {
+    documents: Vec<Document>,
+}
+
+/// 파일 구조
+/// [[id: 4B][len: 8B][word_count: 8B][mtime: 8B][record: 4B][offset: 8B][path_len: 4B][path: path_len B]...]
+/// record는 레코드의 줄 번호이고, 파일 전체가 문서 하나이면 0이다. offset은 레코드의 바이트 위치이다.
+impl DocumentTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 문서를 추가한다.
+    /// 번호 순서가 유지되도록 제자리에 끼워넣는다.
+    pub fn push(&mut self, document: Document) {
+        let i = self
+            .documents
+            .partition_point(|other| other.id < document.id);
+        self.documents.insert(i, document);
+    }
+
+    /// 새로 색인한 문서들을 한꺼번에 추가한다.
+    /// 새 문서의 번호는 `next_id`부터이므로 한 번만 정렬해서 뒤에 붙인다.
+    pub fn extend<I: IntoIterator<Item = Document>>(&mut self, documents: I) {
+        let mut documents: Vec<Document> = documents.into_iter().collect();
+        documents.sort_by_key(|document| document.id);
+        debug_assert!(documents
+            .first()
+            .is_none_or(|document| document.id as usize >= self.next_id()));
+        self.documents.extend(documents);
+    }
+
+    /// 문서를 테이블에서 뺀다.
+    pub fn remove(&mut self, id: u32) -> Option<Document> {
+        self.documents
+            .binary_search_by_key(&id, |document| document.id)
+            .ok()
+            .map(|i| self.documents.remove(i))
+    }
+
+    /// 문서 번호로 문서를 찾는다.
+    pub fn get(&self, id: u32) -> Option<&Document> {
+        self.documents
+            .binary_search_by_key(&id, |document| document.id)
+            .ok()
+            .map(|i| &self.documents[i])
+    }
+
+    /// 새 문서에 매길 번호. 가장 큰 번호의 다음 번호이다.
+    pub fn next_id(&self) -> usize {
+        self.documents
+            .last()
+            .map_or(0, |document| document.id as usize + 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.documents.iter()
+    }
+
+    /// 테이블을 파일에 쓴다.
+    pub fn write<P: AsRef<Path>>(&self, filename: P) -> Result<(), Error> {
+        let filename = filename.as_ref();
+        let mut f = BufWriter::new(File::create(filename).with_path(filename)?);
+        self.documents
+            .iter()
+            .try_for_each(|document| {
+                f.write_u32::<LittleEndian>(document.id)?;
+                f.write_u64::<LittleEndian>(document.len)?;
+                f.write_u64::<LittleEndian>(document.word_count)?;
+                f.write_u64::<LittleEndian>(document.mtime)?;
+                f.write_u32::<LittleEndian>(document.record.unwrap_or(0))?;
+                f.write_u64::<LittleEndian>(document.offset)?;
+                let path = document.path.to_string_lossy();
+                f.write_u32::<LittleEndian>(path.len() as u32)?;
+                f.write_all(path.as_bytes())
+            })
+            .and_then(|()| f.flush())
+            .with_path(filename)
+    }
+
+    /// 파일에서 테이블을 읽는다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<DocumentTable, Error> {
+        let filename = filename.as_ref();
+        DocumentTable::read(BufReader::new(File::open(filename).with_path(filename)?))
+            .with_path(filename)
+    }
+
+    /// 경로 길이가 남은 파일보다 길면 미리 할당하지 않고 `FormatError::Truncated`를 돌려준다.
+    fn read<R: Read>(mut f: R) -> Result<DocumentTable, Error> {
+        let mut table = DocumentTable::new();
+        // 지금 읽는 엔트리의 위치
+        let mut position = 0;
+        loop {
+            let id = match f.read_u32::<LittleEndian>() {
+                Ok(value) => value,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            let len = f.read_u64::<LittleEndian>()?;
+            let word_count = f.read_u64::<LittleEndian>()?;
+            let mtime = f.read_u64::<LittleEndian>()?;
+            let record = f.read_u32::<LittleEndian>()?;
+            let offset = f.read_u64::<LittleEndian>()?;
+            let path_len = f.read_u32::<LittleEndian>()? as u64;
+            let mut bytes = Vec::new();
+            Read::by_ref(&mut f)
+                .take(path_len)
+                .read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != path_len {
+                return Err(FormatError::Truncated {
+                    expected: position + ENTRY_SIZE + path_len,
+                    actual: position + ENTRY_SIZE + bytes.len() as u64,
+                }
+                .into());
+            }
+            position += ENTRY_SIZE + path_len;
+            let path = String::from_utf8(bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            table.push(Document {
+                id,
+                path: PathBuf::from(path),
+                record: (record != 0).then_some(record),
+                offset,
+                len,
+                word_count,
+                mtime,
+            });
+        }
+        Ok(table)
+    }
+}
+
+/// 파일의 수정 시각을 UNIX 시간(초)으로 바꾼다.
+pub fn mtime_of(modified: SystemTime) -> u64 {
+    modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = TestDir::new("documents");
+        let mut table = DocumentTable::new();
+        table.extend([
+            Document {
+                id: 1,
+                path: PathBuf::from("b.jsonl"),
+                record: Some(3),
+                offset: 42,
+                len: 100,
+                word_count: 7,
+                mtime: 1_700_000_000,
+            },
+            Document {
+                id: 0,
+                path: PathBuf::from("문서/a.txt"),
+                record: None,
+                offset: 0,
+                len: 10,
+                word_count: 2,
+                mtime: 0,
+            },
+        ]);
+        let path = dir.join("index.docs");
+        table.write(&path).unwrap();
+
+        let read = DocumentTable::open(&path).unwrap();
+        assert_eq!(
+            read.iter().collect::<Vec<_>>(),
+            table.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(read.get(1).unwrap().record, Some(3));
+
+        // 잘린 경로는 경로 길이만큼 할당하지 않고 잘린 파일로 본다.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let second = ENTRY_SIZE as usize + "문서/a.txt".len();
+        bytes[second + ENTRY_SIZE as usize - 4..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes.truncate(bytes.len() - 2);
+        std::fs::write(&path, &bytes).unwrap();
+        match DocumentTable::open(&path) {
+            Err(Error::CorruptIndex {
+                reason: FormatError::Truncated { expected, actual },
+                ..
+            }) => {
+                assert_eq!(expected, second as u64 + ENTRY_SIZE + u32::MAX as u64);
+                assert_eq!(actual, bytes.len() as u64);
+            }
+            other => panic!("expected truncated table, got {:?}", other.err()),
+        }
+    }
+}
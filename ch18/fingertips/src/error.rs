@@ -0,0 +1,142 @@
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::header::FormatError;
+
+/// fingertips의 오류
+/// 파일과 관련된 오류는 어느 파일인지 알 때 path에 담는다. `PathContext`로 붙인다.
+#[derive(Debug)]
+pub enum Error {
+    /// 파일을 읽거나 쓰지 못했다.
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// 인덱스 파일이 아니거나 손상되었다.
+    CorruptIndex {
+        path: Option<PathBuf>,
+        reason: FormatError,
+    },
+    /// 인덱스의 단어가 UTF-8이 아니다.
+    BadUtf8Term {
+        path: Option<PathBuf>,
+        bytes: Vec<u8>,
+    },
+    /// 색인할 문서가 없거나 단어가 하나도 없다.
+    EmptyCorpus,
+    /// 질의 문법이 틀렸다.
+    InvalidQuery(String),
+    /// 인수나 분석기 설정이 잘못되었다.
+    InvalidArgument(String),
+}
+
+impl Error {
+    /// 경로를 모르는 오류에 path를 붙인다. 이미 경로가 있으면 그대로 둔다.
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Error {
+        match &mut self {
+            Error::Io { path: p @ None, .. }
+            | Error::CorruptIndex { path: p @ None, .. }
+            | Error::BadUtf8Term { path: p @ None, .. } => *p = Some(path.as_ref().to_path_buf()),
+            _ => {}
+        }
+        self
+    }
+
+    /// 프로세스의 종료 코드
+    /// 1은 입출력 오류, 2는 잘못된 입력(clap과 같다), 3은 손상된 인덱스, 4는 색인할 문서가 없을 때이다.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 1,
+            Error::InvalidQuery(_) | Error::InvalidArgument(_) => 2,
+            Error::CorruptIndex { .. } | Error::BadUtf8Term { .. } => 3,
+            Error::EmptyCorpus => 4,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self {
+            Error::Io { path, .. }
+            | Error::CorruptIndex { path, .. }
+            | Error::BadUtf8Term { path, .. } => path.as_deref(),
+            _ => None,
+        };
+        if let Some(path) = path {
+            write!(f, "{}: ", path.display())?;
+        }
+        match self {
+            Error::Io { source, .. } => write!(f, "{}", source),
+            Error::CorruptIndex { reason, .. } => write!(f, "{}", reason),
+            Error::BadUtf8Term { bytes, .. } => write!(
+                f,
+                "term is not valid UTF-8: {:?}",
+                String::from_utf8_lossy(bytes)
+            ),
+            Error::EmptyCorpus => write!(f, "no documents were parsed or none contained any words"),
+            Error::InvalidQuery(message) => write!(f, "query: {}", message),
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::CorruptIndex { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+impl From<FormatError> for Error {
+    fn from(reason: FormatError) -> Self {
+        Error::CorruptIndex { path: None, reason }
+    }
+}
+
+/// 결과의 오류에 경로를 붙인다.
+/// `File::open(path).with_path(path)?` 처럼 쓴다.
+pub trait PathContext<T> {
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> PathContext<T> for Result<T, E> {
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T, Error> {
+        self.map_err(|e| e.into().with_path(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_path() {
+        let e = Error::from(FormatError::BadMagic).with_path("index.dat");
+        assert_eq!(e.to_string(), "index.dat: not a fingertips index file");
+        assert_eq!(e.exit_code(), 3);
+
+        // 안쪽에서 붙인 경로를 바깥에서 덮어쓰지 않는다.
+        let result: Result<(), Error> = Err(io::Error::from(io::ErrorKind::NotFound))
+            .with_path("tmp00000001.dat")
+            .with_path("index.dat");
+        assert!(matches!(
+            result,
+            Err(Error::Io { path: Some(ref path), .. }) if path == Path::new("tmp00000001.dat")
+        ));
+
+        let e = Error::InvalidQuery("unexpected end of query".to_string()).with_path("index.dat");
+        assert_eq!(e.to_string(), "query: unexpected end of query");
+        assert_eq!(e.exit_code(), 2);
+    }
+}
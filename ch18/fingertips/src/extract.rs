@@ -0,0 +1,419 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use pulldown_cmark::{Event, Parser, TagEnd};
+use serde_json::Value;
+
+use crate::{error::Error, field::Field};
+
+/// 올바르지 않은 UTF-8을 만났을 때 할 일
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidUtf8 {
+    /// 읽을 수 없는 바이트를 U+FFFD로 바꾸고 색인한다.
+    #[default]
+    Lossy,
+    /// 파일을 색인하지 않는다.
+    Skip,
+}
+
+/// 파일 내용에서 색인할 텍스트를 뽑는다.
+pub trait Extractor: Send + Sync {
+    fn extract(&self, text: &str) -> String;
+}
+
+/// 내용을 그대로 쓴다.
+pub struct PlainText;
+
+impl Extractor for PlainText {
+    fn extract(&self, text: &str) -> String {
+        text.to_string()
+    }
+}
+
+/// 태그와 주석, script와 style 요소를 빼고 문자 참조를 푼다.
+pub struct Html;
+
+impl Extractor for Html {
+    fn extract(&self, html: &str) -> String {
+        let mut text = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(i) = rest.find('<') {
+            decode_entities(&rest[..i], &mut text);
+            // 태그 양쪽의 단어가 붙지 않게 한다.
+            text.push(' ');
+            rest = skip_tag(&rest[i..]);
+        }
+        decode_entities(rest, &mut text);
+        text
+    }
+}
+
+/// 마크다운 문법을 빼고 본문과 코드만 남긴다.
+pub struct Markdown;
+
+impl Extractor for Markdown {
+    fn extract(&self, markdown: &str) -> String {
+        Parser::new(markdown).fold(String::new(), |mut text, event| {
+            match event {
+                Event::Text(s) | Event::Code(s) => text.push_str(&s),
+                Event::Html(s) | Event::InlineHtml(s) => text.push_str(&Html.extract(&s)),
+                Event::SoftBreak | Event::HardBreak | Event::Rule => text.push(' '),
+                // 강조나 링크가 끝나는 곳은 단어 중간일 수 있다.
+                Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough)
+                | Event::End(TagEnd::Link) => {}
+                Event::End(_) => text.push(' '),
+                _ => {}
+            }
+            text
+        })
+    }
+}
+
+/// 추출기와 레코드 추출이 아는 확장자. .gz를 붙인 파일도 읽는다.
+/// 디렉터리를 훑을 때 --include를 주지 않으면 이 확장자의 파일을 색인한다.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "txt", "html", "htm", "xhtml", "md", "markdown", "jsonl", "ndjson",
+];
+
+/// 확장자로 추출기를 고른다. 모르는 확장자는 일반 텍스트로 읽는다.
+pub fn extractor_for(path: &Path) -> &'static dyn Extractor {
+    match extension(path).as_deref() {
+        Some("html" | "htm" | "xhtml") => &Html,
+        Some("md" | "markdown") => &Markdown,
+        _ => &PlainText,
+    }
+}
+
+/// 파일의 내용을 색인할 텍스트로 바꾼다.
+/// .gz 파일은 압축을 풀고 안쪽의 확장자로 추출기를 고른다. 예: notes.md.gz
+/// UTF-8이 아닌 파일은 invalid_utf8에 따라 바꾸거나 InvalidData 오류를 돌려준다.
+pub fn extract_text(
+    path: &Path,
+    bytes: Vec<u8>,
+    invalid_utf8: InvalidUtf8,
+) -> Result<String, Error> {
+    let (path, bytes) = decompress(path, bytes)?;
+    let text = decode_utf8(bytes, invalid_utf8)?;
+    Ok(extractor_for(&path).extract(&text))
+}
+
+/// 색인할 문서 하나의 필드별 텍스트
+pub type Fields = Vec<(Field, String)>;
+
+/// 파일에서 뽑은 문서 하나
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// JSON-lines 파일에서 레코드가 있는 줄(1부터). 파일 전체가 문서 하나이면 None
+    pub line: Option<u32>,
+    /// 레코드가 시작하는 바이트 위치. .gz 파일은 압축을 푼 내용 기준이다. 파일 전체가 문서 하나이면 0
+    pub offset: u64,
+    /// 레코드의 바이트 수
+    pub len: u64,
+    /// 줄을 해석할 수 없으면 그 이유
+    pub fields: Result<Fields, String>,
+}
+
+/// JSON-lines 파일인지 확장자로 판단한다. .gz 파일은 안쪽의 확장자를 본다.
+pub fn is_json_lines(path: &Path) -> bool {
+    let path = match extension(path).as_deref() {
+        Some("gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    matches!(extension(&path).as_deref(), Some("jsonl" | "ndjson"))
+}
+
+/// 파일의 내용을 색인할 문서들로 바꾼다.
+/// JSON-lines 파일은 빈 줄이 아닌 줄마다 레코드 하나이다. 레코드의 title, body는 문자열이고
+/// tags는 문자열이나 문자열의 배열이다. 다른 키는 무시한다.
+/// 다른 파일은 `extract_text`로 뽑은 텍스트를 본문으로 하는 레코드 하나이다.
+pub fn extract_records(
+    path: &Path,
+    bytes: Vec<u8>,
+    invalid_utf8: InvalidUtf8,
+) -> Result<Vec<Record>, Error> {
+    if !is_json_lines(path) {
+        let len = bytes.len() as u64;
+        let text = extract_text(path, bytes, invalid_utf8)?;
+        return Ok(vec![Record {
+            line: None,
+            offset: 0,
+            len,
+            fields: Ok(vec![(Field::Body, text)]),
+        }]);
+    }
+
+    // 레코드의 위치는 UTF-8로 바꾸기 전의 바이트로 센다.
+    let (_, bytes) = decompress(path, bytes)?;
+    let mut offset = 0;
+    bytes
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let start = offset;
+            offset += line.len() as u64 + 1;
+            (!line.trim_ascii().is_empty()).then_some((i, start, line))
+        })
+        .map(|(i, start, line)| {
+            let text = decode_utf8(line.to_vec(), invalid_utf8)?;
+            Ok(Record {
+                line: Some(i as u32 + 1),
+                offset: start,
+                len: line.len() as u64,
+                fields: parse_record(&text),
+            })
+        })
+        .collect()
+}
+
+/// JSON-lines 파일에서 offset에 있는 레코드 하나를 읽는다. offset은 `Record::offset`이다.
+/// 파일 전체를 읽지 않고 그 줄만 읽는다. .gz 파일은 앞부분을 풀면서 건너뛴다.
+/// offset이 줄의 처음이 아니거나 줄을 해석할 수 없으면(파일이 바뀌었으면) InvalidData 오류를 돌려준다.
+pub fn read_record(path: &Path, offset: u64, invalid_utf8: InvalidUtf8) -> Result<Fields, Error> {
+    // 줄의 처음인지 보려고 바로 앞의 바이트부터 읽는다.
+    let start = offset.saturating_sub(1);
+    let mut f = File::open(path)?;
+    let mut reader: Box<dyn BufRead> = match extension(path).as_deref() {
+        Some("gz") => {
+            let mut reader = BufReader::new(GzDecoder::new(f));
+            io::copy(&mut reader.by_ref().take(start), &mut io::sink())?;
+            Box::new(reader)
+        }
+        _ => {
+            f.seek(SeekFrom::Start(start))?;
+            Box::new(BufReader::new(f))
+        }
+    };
+    let changed = || io::Error::new(io::ErrorKind::InvalidData, "record has changed");
+    if offset > 0 {
+        let mut before = [0];
+        reader.read_exact(&mut before)?;
+        if before != *b"\n" {
+            return Err(changed().into());
+        }
+    }
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    let line = line.strip_suffix(b"\n").unwrap_or(&line);
+    if line.trim_ascii().is_empty() {
+        return Err(changed().into());
+    }
+    let text = decode_utf8(line.to_vec(), invalid_utf8)?;
+    parse_record(&text).map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, reason).into())
+}
+
+/// JSON 객체 하나를 필드로 나눈다.
+fn parse_record(line: &str) -> Result<Fields, String> {
+    let object = match serde_json::from_str(line).map_err(|e| e.to_string())? {
+        Value::Object(object) => object,
+        _ => return Err("record is not a JSON object".to_string()),
+    };
+    Ok(Field::ALL
+        .into_iter()
+        .filter_map(|field| {
+            let text = match object.get(field.name())? {
+                Value::String(text) => text.clone(),
+                Value::Array(values) if field == Field::Tags => values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => return None,
+            };
+            Some((field, text))
+        })
+        .collect())
+}
+
+/// .gz 파일은 압축을 풀고 .gz를 뺀 경로와 함께 돌려준다.
+fn decompress(path: &Path, bytes: Vec<u8>) -> Result<(PathBuf, Vec<u8>), Error> {
+    if extension(path).as_deref() != Some("gz") {
+        return Ok((path.to_path_buf(), bytes));
+    }
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&bytes[..]).read_to_end(&mut decompressed)?;
+    Ok((path.with_extension(""), decompressed))
+}
+
+fn decode_utf8(bytes: Vec<u8>, invalid_utf8: InvalidUtf8) -> Result<String, Error> {
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) if invalid_utf8 == InvalidUtf8::Lossy => {
+            Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+        }
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.utf8_error()).into()),
+    }
+}
+
+/// 소문자로 바꾼 확장자
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
+
+/// '<'로 시작하는 태그를 건너뛰고 나머지를 돌려준다.
+/// 주석과 script, style 요소는 내용까지 건너뛴다.
+fn skip_tag(html: &str) -> &str {
+    if let Some(comment) = html.strip_prefix("<!--") {
+        return comment.find("-->").map_or("", |i| &comment[i + 3..]);
+    }
+    let after = html.find('>').map_or("", |i| &html[i + 1..]);
+    let name = html[1..]
+        .split(|ch: char| ch.is_whitespace() || ch == '>' || ch == '/')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match name.as_str() {
+        "script" | "style" => {
+            // ASCII만 바꾸므로 소문자로 바꿔도 바이트 위치는 같다.
+            match after.to_ascii_lowercase().find(&format!("</{}", name)) {
+                Some(i) => skip_tag(&after[i..]),
+                None => "",
+            }
+        }
+        _ => after,
+    }
+}
+
+/// 문자 참조를 풀어서 out에 더한다. 모르는 참조는 그대로 둔다.
+fn decode_entities(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| entity(&rest[1..end]).map(|ch| (ch, end)));
+        match decoded {
+            Some((ch, end)) => {
+                out.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+}
+
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            };
+            code.and_then(char::from_u32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+    use crate::test_util::TestDir;
+
+    fn words(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn test_extract_text() {
+        let html = "<html><head><style>p { color: red }</style>\
+            <SCRIPT>var x = 1;</SCRIPT></head>\
+            <body><!-- note --><p>Fish&amp;chips</p><p>caf&#233; &lt;3</p></body></html>";
+        assert_eq!(words(&Html.extract(html)), ["Fish&chips", "café", "<3"]);
+
+        let markdown = "# Gap buffers\n\nA *gap* buffer [holds](http://x) `text`.\n";
+        assert_eq!(
+            words(&Markdown.extract(markdown)),
+            ["Gap", "buffers", "A", "gap", "buffer", "holds", "text."]
+        );
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(b"<b>bold</b> move").unwrap();
+        let bytes = gz.finish().unwrap();
+        let text = extract_text(Path::new("a.html.gz"), bytes, InvalidUtf8::Skip).unwrap();
+        assert_eq!(words(&text), ["bold", "move"]);
+
+        let invalid = b"ok \xff".to_vec();
+        assert_eq!(
+            extract_text(Path::new("a.txt"), invalid.clone(), InvalidUtf8::Lossy).unwrap(),
+            "ok \u{fffd}"
+        );
+        assert!(extract_text(Path::new("a.txt"), invalid, InvalidUtf8::Skip).is_err());
+    }
+
+    #[test]
+    fn test_extract_records() {
+        let jsonl =
+            "{\"title\": \"Gap buffers\", \"body\": \"A gap\", \"tags\": [\"editor\", \"text\"]}\n\
+            \n\
+            not json\n\
+            {\"tags\": \"rope\", \"stars\": 3}\n";
+        let path = Path::new("posts.JSONL");
+        assert!(is_json_lines(Path::new("posts.ndjson.gz")));
+        let whole = extract_records(Path::new("a.json"), jsonl.into(), InvalidUtf8::Skip);
+        assert_eq!(whole.unwrap().len(), 1);
+
+        let records = extract_records(path, jsonl.into(), InvalidUtf8::Skip).unwrap();
+        assert_eq!(
+            records.iter().map(|record| record.line).collect::<Vec<_>>(),
+            [Some(1), Some(3), Some(4)]
+        );
+        assert!(jsonl[records[1].offset as usize..].starts_with("not json\n"));
+        assert!(jsonl[records[2].offset as usize..].starts_with("{\"tags\""));
+        assert_eq!(
+            records[0].fields,
+            Ok(vec![
+                (Field::Body, "A gap".to_string()),
+                (Field::Title, "Gap buffers".to_string()),
+                (Field::Tags, "editor, text".to_string()),
+            ])
+        );
+        assert!(records[1].fields.is_err());
+        assert_eq!(
+            records[2].fields,
+            Ok(vec![(Field::Tags, "rope".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_read_record() {
+        let jsonl = "{\"title\": \"Gap\"}\n\n{\"tags\": [\"rope\"], \"body\": \"trees\"}\n";
+        let records =
+            extract_records(Path::new("a.jsonl"), jsonl.into(), InvalidUtf8::Skip).unwrap();
+        let dir = TestDir::new("extract");
+        let plain = dir.join("posts.jsonl");
+        std::fs::write(&plain, jsonl).unwrap();
+        let compressed = dir.join("posts.jsonl.gz");
+        let mut gz = GzEncoder::new(File::create(&compressed).unwrap(), Compression::default());
+        gz.write_all(jsonl.as_bytes()).unwrap();
+        gz.finish().unwrap();
+
+        [&plain, &compressed].iter().for_each(|path| {
+            records.iter().for_each(|record| {
+                let fields = read_record(path, record.offset, InvalidUtf8::Skip).unwrap();
+                assert_eq!(Ok(fields), record.fields);
+            });
+            assert!(read_record(path, 3, InvalidUtf8::Skip).is_err());
+            assert!(read_record(path, 16, InvalidUtf8::Skip).is_err());
+        });
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::analysis::{Analyzer, Token};
+
+/// 문서 안의 필드
+/// 번호는 인덱스의 Hit에 기록되므로 바꾸면 안 된다.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    /// 본문. 일반 파일은 내용 전체가 본문이다.
+    Body = 0,
+    Title = 1,
+    Tags = 2,
+}
+
+/// 한 필드의 마지막 위치와 다음 필드의 첫 위치 사이에 비워두는 위치 수
+/// 구절 검색이 필드 경계를 넘어서 맞지 않게 한다.
+pub const FIELD_POSITION_GAP: u32 = 100;
+
+impl Field {
+    /// 번호 순으로 나열한 모든 필드
+    pub const ALL: [Field; 3] = [Field::Body, Field::Title, Field::Tags];
+
+    /// Hit에 기록하는 번호
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_id(id: u32) -> Option<Field> {
+        Field::ALL.get(id as usize).copied()
+    }
+
+    /// 질의와 JSON 레코드에서 쓰는 이름
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Body => "body",
+            Field::Title => "title",
+            Field::Tags => "tags",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.name() == name)
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 필드별 텍스트를 분석한다. 토큰의 바이트 위치는 그 필드의 텍스트 기준이다.
+/// 필드는 번호 순으로 나누고, 앞 필드의 마지막 위치에서 FIELD_POSITION_GAP만큼 띄운 곳부터 위치를 매긴다.
+/// 그래서 문서 안의 위치는 필드 번호 순으로 커지고, 본문만 있는 문서는 `Analyzer::analyze`와 위치가 같다.
+pub fn analyze_fields(analyzer: &dyn Analyzer, fields: &[(Field, String)]) -> Vec<(Field, Token)> {
+    let mut fields = fields.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|(field, _)| *field);
+
+    let mut tokens = Vec::new();
+    fields.into_iter().fold(0, |start, (field, text)| {
+        let analyzed = analyzer.analyze(text);
+        let next = analyzed
+            .iter()
+            .map(|token| start + token.position + 1 + FIELD_POSITION_GAP)
+            .max()
+            .unwrap_or(start);
+        tokens.extend(analyzed.into_iter().map(|token| {
+            (
+                *field,
+                Token {
+                    position: start + token.position,
+                    ..token
+                },
+            )
+        }));
+        next
+    });
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Pipeline;
+
+    #[test]
+    fn test_analyze_fields() {
+        let analyzer = Pipeline::default();
+        let fields = [
+            (Field::Tags, "editor".to_string()),
+            (Field::Body, "gap buffer".to_string()),
+            (Field::Title, "Ropes".to_string()),
+        ];
+        let positions = analyze_fields(&analyzer, &fields)
+            .into_iter()
+            .map(|(field, token)| (field, token.text, token.position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                (Field::Body, "gap".to_string(), 0),
+                (Field::Body, "buffer".to_string(), 1),
+                (Field::Title, "ropes".to_string(), 102),
+                (Field::Tags, "editor".to_string(), 203),
+            ]
+        );
+        assert_eq!(Field::from_name("title"), Some(Field::Title));
+        assert_eq!(Field::from_id(Field::Tags.id()), Some(Field::Tags));
+        assert_eq!(Field::from_id(3), None);
+    }
+}
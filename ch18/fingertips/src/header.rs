@@ -0,0 +1,276 @@
+use std::{
+    error, fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::Error;
+
+/// 인덱스 파일의 맨 앞에 쓰는 값
+pub const MAGIC: &[u8; 8] = b"FNGRTIPS";
+
+/// 인덱스 파일 형식의 버전
+/// 1은 헤더 없이 위치를 압축하지 않던 형식, 2는 매직과 체크섬이 없던 형식,
+/// 3은 분석기 설정이 없던 형식, 4는 Hit에 필드 번호가 없던 형식이다.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// 헤더에서 길이가 정해진 부분
+/// [magic: 8B][format_version: 4B][term_count: 8B][offset: 8B][main_crc: 4B][contents_crc: 4B]
+/// 그 뒤에 [analyzer_len: 4B][analyzer: analyzer_len B]가 온다.
+pub const HEADER_SIZE: u64 = 8 + 4 + 8 + 8 + 4 + 4;
+
+/// 버전 뒤에 오는 값들의 위치. 파일을 다 쓴 뒤에 채운다.
+pub const PATCH_OFFSET: u64 = 8 + 4;
+
+/// 인덱스 파일의 헤더
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// 테이블의 엔트리 수
+    pub term_count: u64,
+    /// 테이블의 시작 위치
+    pub contents_offset: u64,
+    /// 인덱스 데이터의 CRC-32
+    pub main_crc: u32,
+    /// 테이블의 CRC-32
+    pub contents_crc: u32,
+    /// 색인할 때 쓴 분석기의 설정. `analysis::Pipeline::from_spec` 참고
+    pub analyzer: String,
+}
+
+/// 인덱스 파일의 어느 부분인지
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Main,
+    Contents,
+}
+
+/// 인덱스 파일이 아니거나 손상되었을 때의 오류
+/// `error::Error::CorruptIndex`에 담겨서 전달된다.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// 매직이 맞지 않는다.
+    BadMagic,
+    /// 읽을 수 없는 형식의 버전
+    UnsupportedVersion(u32),
+    /// 파일이 헤더에 적힌 것보다 짧다.
+    Truncated { expected: u64, actual: u64 },
+    /// 체크섬이 맞지 않는다.
+    ChecksumMismatch(Section),
+    /// 테이블의 엔트리 수가 헤더와 다르다.
+    TermCountMismatch { expected: u64, actual: u64 },
+    /// 헤더의 분석기 설정이 UTF-8이 아니다.
+    BadAnalyzer,
+    /// Posting 목록을 디코딩할 수 없다.
+    BadPostings(&'static str),
+    /// 테이블의 단어가 정렬되어 있지 않다.
+    UnsortedTerms,
+    /// 엔트리의 인덱스 데이터가 메모리에 들어가지 않는다.
+    EntryTooLarge(u64),
+    /// 병합할 파일들의 분석기가 다르다.
+    AnalyzerMismatch { expected: String, actual: String },
+    /// 인덱스 데이터가 앞 엔트리의 끝에서 시작하지 않는다.
+    NotContiguous { expected: u64, actual: u64 },
+    /// 엔트리의 문서 수가 Posting 목록과 다르다.
+    DfMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "not a fingertips index file"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported index format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            FormatError::Truncated { expected, actual } => write!(
+                f,
+                "index file is truncated: expected at least {} bytes, found {}",
+                expected, actual
+            ),
+            FormatError::ChecksumMismatch(section) => {
+                write!(f, "checksum mismatch in {:?} section", section)
+            }
+            FormatError::TermCountMismatch { expected, actual } => write!(
+                f,
+                "term count mismatch: header says {}, table has {}",
+                expected, actual
+            ),
+            FormatError::BadAnalyzer => write!(f, "analyzer spec is not valid UTF-8"),
+            FormatError::BadPostings(reason) => write!(f, "corrupt posting list: {}", reason),
+            FormatError::UnsortedTerms => write!(f, "terms are not sorted"),
+            FormatError::EntryTooLarge(nbytes) => write!(
+                f,
+                "computer not big enough to hold index entry of {} bytes",
+                nbytes
+            ),
+            FormatError::AnalyzerMismatch { expected, actual } => write!(
+                f,
+                "cannot merge index files built with different analyzers: {:?} and {:?}",
+                expected, actual
+            ),
+            FormatError::NotContiguous { expected, actual } => {
+                write!(f, "data starts at offset {}, expected {}", actual, expected)
+            }
+            FormatError::DfMismatch { expected, actual } => write!(
+                f,
+                "df mismatch: table says {}, posting list has {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl error::Error for FormatError {}
+
+impl Header {
+    /// 헤더를 읽고 매직과 버전을 확인한다.
+    pub fn read<R: Read>(f: &mut R) -> Result<Header, Error> {
+        let mut magic = [0; 8];
+        match f.read_exact(&mut magic) {
+            Ok(()) if magic == *MAGIC => {}
+            Ok(()) => return Err(FormatError::BadMagic.into()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(FormatError::BadMagic.into())
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let version = f.read_u32::<LittleEndian>()?;
+        if version != FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version).into());
+        }
+        let term_count = f.read_u64::<LittleEndian>()?;
+        let contents_offset = f.read_u64::<LittleEndian>()?;
+        let main_crc = f.read_u32::<LittleEndian>()?;
+        let contents_crc = f.read_u32::<LittleEndian>()?;
+        let analyzer_len = f.read_u32::<LittleEndian>()? as usize;
+        // 체크섬을 확인하기 전이므로 analyzer_len만큼 미리 할당하지 않고 읽은 만큼만 늘린다.
+        let mut analyzer = Vec::new();
+        Read::by_ref(f)
+            .take(analyzer_len as u64)
+            .read_to_end(&mut analyzer)?;
+        if analyzer.len() != analyzer_len {
+            return Err(FormatError::Truncated {
+                expected: HEADER_SIZE + 4 + analyzer_len as u64,
+                actual: HEADER_SIZE + 4 + analyzer.len() as u64,
+            }
+            .into());
+        }
+        Ok(Header {
+            term_count,
+            contents_offset,
+            main_crc,
+            contents_crc,
+            analyzer: String::from_utf8(analyzer).map_err(|_| FormatError::BadAnalyzer)?,
+        })
+    }
+
+    /// 헤더 전체의 바이트 수. 인덱스 데이터는 여기서 시작한다.
+    pub fn size(&self) -> u64 {
+        Header::size_with(&self.analyzer)
+    }
+
+    /// analyzer를 기록한 헤더의 바이트 수
+    pub fn size_with(analyzer: &str) -> u64 {
+        HEADER_SIZE + 4 + analyzer.len() as u64
+    }
+
+    /// 비어 있는 헤더와 분석기 설정을 쓴다. 값은 `write_patch`로 나중에 채운다.
+    pub fn write_placeholder<W: Write>(f: &mut W, analyzer: &str) -> Result<(), Error> {
+        f.write_all(MAGIC)?;
+        f.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+        f.write_all(&[0; (HEADER_SIZE - PATCH_OFFSET) as usize])?;
+        f.write_u32::<LittleEndian>(analyzer.len() as u32)?;
+        f.write_all(analyzer.as_bytes())?;
+        Ok(())
+    }
+
+    /// 버전 뒤의 값들을 쓴다. f는 PATCH_OFFSET에 있어야 한다.
+    pub fn write_patch<W: Write>(&self, f: &mut W) -> Result<(), Error> {
+        f.write_u64::<LittleEndian>(self.term_count)?;
+        f.write_u64::<LittleEndian>(self.contents_offset)?;
+        f.write_u32::<LittleEndian>(self.main_crc)?;
+        f.write_u32::<LittleEndian>(self.contents_crc)?;
+        Ok(())
+    }
+
+    /// 파일을 처음부터 읽어서 헤더를 확인하고 체크섬을 검사한다.
+    /// 끝나면 파일의 위치는 테이블의 시작이다.
+    pub fn read_verified(f: &mut File) -> Result<Header, Error> {
+        let header = Header::read_bounded(f)?;
+        let main_crc = crc_of(Read::by_ref(f).take(header.contents_offset - header.size()))?;
+        if main_crc != header.main_crc {
+            return Err(FormatError::ChecksumMismatch(Section::Main).into());
+        }
+        let contents_crc = crc_of(&mut *f)?;
+        if contents_crc != header.contents_crc {
+            return Err(FormatError::ChecksumMismatch(Section::Contents).into());
+        }
+
+        f.seek(SeekFrom::Start(header.contents_offset))?;
+        Ok(header)
+    }
+
+    /// 파일을 처음부터 읽어서 헤더를 확인하고, 테이블의 위치가 파일 안에 있는지만 본다. 체크섬은 검사하지 않는다.
+    /// 끝나면 파일의 위치는 인덱스 데이터의 시작이다.
+    pub fn read_bounded(f: &mut File) -> Result<Header, Error> {
+        let len = f.metadata()?.len();
+        f.seek(SeekFrom::Start(0))?;
+        let header = Header::read(f).map_err(|e| match e {
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::UnexpectedEof => {
+                FormatError::Truncated {
+                    expected: HEADER_SIZE,
+                    actual: len,
+                }
+                .into()
+            }
+            e => e,
+        })?;
+        if header.contents_offset < header.size() || header.contents_offset > len {
+            return Err(FormatError::Truncated {
+                expected: header.contents_offset.max(header.size()),
+                actual: len,
+            }
+            .into());
+        }
+        Ok(header)
+    }
+}
+
+/// 끝까지 읽으면서 CRC-32를 구한다.
+fn crc_of<R: Read>(mut f: R) -> Result<u32, Error> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match f.read(&mut buf)? {
+            0 => return Ok(hasher.finalize()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bad_analyzer_len() {
+        let mut bytes = Vec::new();
+        Header::write_placeholder(&mut bytes, "lowercase").unwrap();
+        assert_eq!(Header::read(&mut &bytes[..]).unwrap().analyzer, "lowercase");
+
+        bytes[HEADER_SIZE as usize..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        match Header::read(&mut &bytes[..]) {
+            Err(Error::CorruptIndex {
+                reason: FormatError::Truncated { expected, actual },
+                ..
+            }) => {
+                assert_eq!(expected, HEADER_SIZE + 4 + u32::MAX as u64);
+                assert_eq!(actual, bytes.len() as u64);
+            }
+            other => panic!("expected truncated index, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashMap, fs, ops::Range};
+
+use serde::Serialize;
+
+use crate::{
+    analysis::{Analyzer, Token},
+    documents::Document,
+    error::{Error, PathContext},
+    extract::{extract_text, read_record, InvalidUtf8},
+    field::{analyze_fields, Field},
+    search::IndexSearcher,
+};
+
+/// 검색 결과의 위치로 원문에서 스니펫을 잘라내는 도구
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    /// 맞은 토큰 앞뒤로 보여줄 토큰 수
+    pub context: u32,
+    /// 문서 하나에서 돌려줄 스니펫 수
+    pub max_snippets: usize,
+    /// 맞은 단어 앞에 넣을 표시
+    pub pre: String,
+    /// 맞은 단어 뒤에 넣을 표시
+    pub post: String,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Highlighter {
+            context: 5,
+            max_snippets: 3,
+            pre: "<mark>".to_string(),
+            post: "</mark>".to_string(),
+        }
+    }
+}
+
+/// 원문의 한 부분
+/// 바이트 위치는 추출한 필드 텍스트 기준이다. HTML이나 마크다운은 원본 파일과 위치가 다르다.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snippet {
+    /// 스니펫을 잘라낸 필드
+    pub field: Field,
+    /// 맞은 단어를 표시한 텍스트. 앞이나 뒤에 단어가 더 있으면 …를 붙인다.
+    pub text: String,
+    /// 스니펫의 바이트 위치
+    pub start: usize,
+    pub end: usize,
+    /// 맞은 단어의 바이트 위치
+    pub matches: Vec<Range<usize>>,
+}
+
+impl Highlighter {
+    /// 문서의 파일을 다시 읽어서 스니펫을 만든다. 스니펫은 필드 번호 순이다.
+    /// JSON-lines 레코드는 문서 테이블에 적어둔 위치의 줄만 읽는다.
+    /// analyzer는 색인할 때 쓴 분석기여야 위치가 맞는다.
+    pub fn highlight_document(
+        &self,
+        document: &Document,
+        analyzer: &dyn Analyzer,
+        positions: &[u32],
+        invalid_utf8: InvalidUtf8,
+    ) -> Result<Vec<Snippet>, Error> {
+        let path = &document.path;
+        let fields = match document.record {
+            Some(_) => read_record(path, document.offset, invalid_utf8),
+            None => fs::read(path)
+                .map_err(Error::from)
+                .and_then(|bytes| extract_text(path, bytes, invalid_utf8))
+                .map(|text| vec![(Field::Body, text)]),
+        }
+        .with_path(path)?;
+
+        let tokens = analyze_fields(analyzer, &fields);
+        Ok(fields
+            .iter()
+            .flat_map(|(field, text)| {
+                let field_tokens = tokens
+                    .iter()
+                    .filter(|(f, _)| f == field)
+                    .map(|(_, token)| token.clone())
+                    .collect::<Vec<_>>();
+                self.snippets_of(*field, text, &field_tokens, positions)
+            })
+            .take(self.max_snippets)
+            .collect())
+    }
+
+    /// text를 다시 분석해서 토큰 위치를 바이트 위치로 바꾸고 positions 주변을 잘라낸다.
+    /// 가까운 위치는 한 스니펫으로 묶는다. 텍스트에 없는 위치는 무시한다.
+    pub fn snippets(&self, text: &str, analyzer: &dyn Analyzer, positions: &[u32]) -> Vec<Snippet> {
+        self.snippets_of(Field::Body, text, &analyzer.analyze(text), positions)
+    }
+
+    /// tokens는 필드의 text를 분석한 토큰이다.
+    fn snippets_of(
+        &self,
+        field: Field,
+        text: &str,
+        tokens: &[Token],
+        positions: &[u32],
+    ) -> Vec<Snippet> {
+        // n-gram처럼 한 위치에 여러 토큰이 있으면 모두 덮는 범위를 쓴다.
+        let ranges = tokens.iter().fold(HashMap::new(), |mut ranges, token| {
+            ranges
+                .entry(token.position)
+                .and_modify(|range: &mut Range<usize>| {
+                    range.start = range.start.min(token.start);
+                    range.end = range.end.max(token.end);
+                })
+                .or_insert(token.start..token.end);
+            ranges
+        });
+
+        let mut positions = positions
+            .iter()
+            .copied()
+            .filter(|position| ranges.contains_key(position))
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions.dedup();
+
+        // 앞뒤 context가 겹치는 위치를 묶는다.
+        let mut windows: Vec<(u32, u32, Vec<u32>)> = Vec::new();
+        positions.into_iter().for_each(|position| {
+            let low = position.saturating_sub(self.context);
+            match windows.last_mut() {
+                Some((_, high, matched)) if low <= *high => {
+                    *high = position.saturating_add(self.context);
+                    matched.push(position);
+                }
+                _ => windows.push((low, position.saturating_add(self.context), vec![position])),
+            }
+        });
+
+        windows
+            .into_iter()
+            .take(self.max_snippets)
+            .map(|(low, high, matched)| {
+                let covered = tokens
+                    .iter()
+                    .filter(|token| (low..=high).contains(&token.position));
+                let start = covered.clone().map(|token| token.start).min().unwrap_or(0);
+                let end = covered.map(|token| token.end).max().unwrap_or(text.len());
+                let matches = matched
+                    .iter()
+                    .map(|position| ranges[position].clone())
+                    .collect();
+                self.snippet(field, text, start..end, matches)
+            })
+            .collect()
+    }
+
+    /// text[range]에서 matches를 표시한 스니펫
+    fn snippet(
+        &self,
+        field: Field,
+        text: &str,
+        range: Range<usize>,
+        matches: Vec<Range<usize>>,
+    ) -> Snippet {
+        let mut marked = String::new();
+        if text[..range.start].chars().any(char::is_alphanumeric) {
+            marked.push('…');
+        }
+        let at = matches.iter().fold(range.start, |at, m| {
+            // 겹치는 n-gram은 앞의 표시에 포함된다.
+            if m.start < at {
+                return at;
+            }
+            marked.push_str(&text[at..m.start]);
+            marked.push_str(&self.pre);
+            marked.push_str(&text[m.start..m.end]);
+            marked.push_str(&self.post);
+            m.end
+        });
+        marked.push_str(&text[at..range.end]);
+        if text[range.end..].chars().any(char::is_alphanumeric) {
+            marked.push('…');
+        }
+
+        Snippet {
+            field,
+            text: marked,
+            start: range.start,
+            end: range.end,
+            matches,
+        }
+    }
+}
+
+/// 문서에서 질의의 단어들이 나온 위치
+/// 구절이나 NEAR의 결과에는 일부 단어의 위치만 남으므로, 단어마다 다시 찾아서 모두 표시한다.
+/// terms는 `query::Query::terms`로 구한 인덱스의 단어이다.
+pub fn term_positions(
+    searcher: &IndexSearcher,
+    terms: &[String],
+    document_id: u32,
+) -> Result<Vec<u32>, Error> {
+    terms.iter().try_fold(Vec::new(), |mut positions, term| {
+        let postings = searcher.postings(term)?;
+        if let Ok(i) = postings.binary_search_by_key(&document_id, |p| p.document_id) {
+            positions.extend(&postings[i].positions);
+        }
+        Ok(positions)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Pipeline;
+
+    #[test]
+    fn test_snippets() {
+        let text = "A gap buffer keeps a gap at the cursor. Ropes are trees. \
+                    Piece tables keep the original text and a buffer of additions.";
+        let analyzer = Pipeline::default();
+        let highlighter = Highlighter {
+            context: 2,
+            pre: "[".to_string(),
+            post: "]".to_string(),
+            ..Highlighter::default()
+        };
+
+        // gap(1), buffer(2), gap(5), buffer(20)
+        let snippets = highlighter.snippets(text, &analyzer, &[1, 2, 5, 20]);
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0].text, "A [gap] [buffer] keeps a [gap] at the…");
+        assert_eq!(snippets[0].matches, [2..5, 6..12, 21..24]);
+        assert_eq!(
+            &text[snippets[0].start..snippets[0].end],
+            "A gap buffer keeps a gap at the"
+        );
+        assert_eq!(snippets[1].text, "…and a [buffer] of additions");
+
+        assert!(highlighter.snippets(text, &analyzer, &[100]).is_empty());
+
+        let one = Highlighter {
+            max_snippets: 1,
+            ..highlighter
+        };
+        assert_eq!(one.snippets(text, &analyzer, &[1, 20]).len(), 1);
+
+        // 아주 큰 context는 넘치지 않고 본문 전체를 덮는다.
+        let whole = Highlighter {
+            context: u32::MAX,
+            ..one
+        };
+        let snippets = whole.snippets(text, &analyzer, &[1, 20]);
+        assert_eq!((snippets[0].start, snippets[0].end), (0, text.len() - 1));
+    }
+}
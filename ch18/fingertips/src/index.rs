@@ -1,66 +1,119 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+};
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use crate::{
+    analysis::Analyzer,
+    error::Error,
+    field::{analyze_fields, Field},
+    header::FormatError,
+    varint::{read_varint, read_varint_u32, skip_varints, write_varint},
+};
+
+/// --memory-limit를 지정하지 않았을 때 메모리에 모을 인덱스의 크기
+pub const DEFAULT_MEMORY_LIMIT: usize = 512 << 20;
 
 #[derive(Default)]
 pub struct InMemoryIndex {
     // 문서의 단어수
     pub word_count: usize,
     pub map: HashMap<String, Vec<Hit>>,
+    /// map이 차지하는 힙 메모리의 대략적인 바이트 수
+    bytes: usize,
 }
 
+/// 문서 하나에서 단어가 나온 위치
+/// 문서가 하나뿐인 Posting 목록이다. 구조는 `PostingListBuilder` 참고
 pub type Hit = Vec<u8>;
 
+/// 디코딩한 Hit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Posting {
+    pub document_id: u32,
+    /// 문서 안에서 단어의 위치(토큰 번호)
+    pub positions: Vec<u32>,
+}
+
 impl InMemoryIndex {
     pub fn new() -> Self {
         Self::default()
     }
 
-    // 문서의 인덱스를 생성한다.
-    pub fn from_single_document(document_id: usize, text: String) -> InMemoryIndex {
+    // 문서의 인덱스를 생성한다. 단어는 analyzer로 나눈다.
+    // 텍스트는 모두 본문 필드에 넣는다.
+    pub fn from_single_document(
+        document_id: usize,
+        text: String,
+        analyzer: &dyn Analyzer,
+    ) -> InMemoryIndex {
+        InMemoryIndex::from_fields(document_id, &[(Field::Body, text)], analyzer)
+    }
+
+    /// 필드가 여러 개인 문서의 인덱스를 생성한다.
+    /// 단어의 Hit에는 그 단어가 나온 필드마다 위치를 따로 담는다. 위치는 `field::analyze_fields` 참고
+    pub fn from_fields(
+        document_id: usize,
+        fields: &[(Field, String)],
+        analyzer: &dyn Analyzer,
+    ) -> InMemoryIndex {
         let document_id = document_id as u32;
-        let text = text.to_lowercase();
-        let tokens = tokenize(&text);
-        let index =
-            tokens
+        let tokens = analyze_fields(analyzer, fields);
+        let mut positions = tokens.iter().fold(
+            HashMap::<&str, BTreeMap<Field, Vec<u32>>>::new(),
+            |mut positions, (field, token)| {
+                positions
+                    .entry(&token.text)
+                    .or_default()
+                    .entry(*field)
+                    .or_default()
+                    .push(token.position);
+                positions
+            },
+        );
+        // n-gram은 한 위치에서 같은 단어가 여러 번 나올 수 있다.
+        positions
+            .values_mut()
+            .flat_map(|fields| fields.values_mut())
+            .for_each(|positions| positions.dedup());
+        let map = positions
+            .into_iter()
+            .map(|(token, fields)| (token.to_string(), vec![encode_hit(document_id, &fields)]))
+            .collect::<HashMap<_, _>>();
+        InMemoryIndex {
+            word_count: tokens.len(),
+            bytes: map
                 .iter()
-                .enumerate()
-                .fold(InMemoryIndex::new(), |mut index, (i, token)| {
-                    let hits = index.map.entry(token.to_string()).or_insert_with(|| {
-                        // hit [document_id: 4B][i: B](n)
-                        let mut hits: Vec<u8> = Vec::with_capacity(4 + 4);
-                        hits.write_u32::<LittleEndian>(document_id).unwrap();
-                        vec![hits]
-                    });
-                    hits[0].write_u32::<LittleEndian>(i as u32).unwrap();
-                    index.word_count += 1;
-                    index
-                });
-
-        if document_id % 100 == 0 {
-            println!(
-                "indexed document {}, {} bytes, {} words",
-                document_id,
-                text.len(),
-                index.word_count
-            );
+                .map(|(term, hits)| term_bytes(term) + hits.iter().map(hit_bytes).sum::<usize>())
+                .sum(),
+            map,
         }
-
-        index
     }
 
     pub fn merge(&mut self, other: InMemoryIndex) {
         other.map.into_iter().for_each(|(term, hits)| {
-            self.map.entry(term).or_default().extend(hits);
+            self.bytes += hits.iter().map(hit_bytes).sum::<usize>();
+            match self.map.get_mut(&term) {
+                Some(existing) => existing.extend(hits),
+                None => {
+                    self.bytes += term_bytes(&term);
+                    self.map.insert(term, hits);
+                }
+            }
         });
 
         self.word_count += other.word_count;
     }
 
-    // 인덱스의 크기가 크면 true를 반환한다.
-    pub fn is_large(&self) -> bool {
-        const REASONABLE_SIZE: usize = 100_000_000;
-        self.word_count > REASONABLE_SIZE
+    /// 인덱스가 차지하는 메모리의 대략적인 바이트 수
+    /// 단어 문자열과 Hit 버퍼, 그리고 이들을 담는 컨테이너의 크기를 더한 값이다.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // 인덱스가 memory_limit 바이트보다 크면 true를 반환한다.
+    pub fn is_large(&self, memory_limit: usize) -> bool {
+        self.bytes > memory_limit
     }
 
     // 인덱스가 비었을 때 true를 반환한다.
@@ -69,8 +122,229 @@ impl InMemoryIndex {
     }
 }
 
-fn tokenize(text: &str) -> Vec<&str> {
-    text.split(|ch: char| !ch.is_alphanumeric())
-        .filter(|word| !word.is_empty())
-        .collect()
+/// 단어 하나가 map에서 차지하는 메모리. 해시 테이블의 빈 칸은 세지 않는다.
+fn term_bytes(term: &str) -> usize {
+    term.len() + mem::size_of::<String>() + mem::size_of::<Vec<Hit>>()
+}
+
+/// Hit 하나가 차지하는 메모리
+fn hit_bytes(hit: &Hit) -> usize {
+    hit.capacity() + mem::size_of::<Hit>()
+}
+
+/// 문서 하나의 Hit를 만든다.
+fn encode_hit(document_id: u32, fields: &BTreeMap<Field, Vec<u32>>) -> Hit {
+    let mut builder = PostingListBuilder::new();
+    fields
+        .iter()
+        .try_for_each(|(&field, positions)| builder.push_positions(document_id, field, positions))
+        .unwrap();
+    builder.into_bytes()
+}
+
+/// 위치를 디코딩하지 않은 Hit. 문서 하나의 필드 하나이다.
+/// positions는 [positions_len: varint][position 차이: varint](n) 이다.
+#[derive(Debug, Clone, Copy)]
+pub struct RawHit<'a> {
+    pub document_id: u32,
+    pub field: Field,
+    positions: &'a [u8],
+}
+
+/// 디스크에 쓰는 단어 하나의 Posting 목록을 만든다.
+/// 문서 번호는 앞 문서와의 차이를 쓰므로 (문서 번호, 필드) 순으로 넣어야 한다.
+/// 같은 문서의 다음 필드는 차이가 0이다.
+/// [document_id 차이: varint][field: varint][positions_len: varint][position 차이: varint](n) ...
+#[derive(Default)]
+pub struct PostingListBuilder {
+    buf: Vec<u8>,
+    last: Option<(u32, Field)>,
+    df: u32,
+}
+
+impl PostingListBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push_key(&mut self, document_id: u32, field: Field) -> Result<(), Error> {
+        let delta = match self.last {
+            Some(last) if (document_id, field) <= last => {
+                return Err(FormatError::BadPostings("document ids are out of order").into())
+            }
+            Some((last, _)) => document_id - last,
+            None => document_id,
+        };
+        write_varint(&mut self.buf, delta as u64);
+        write_varint(&mut self.buf, field.id() as u64);
+        if self.last.is_none_or(|(last, _)| last != document_id) {
+            self.df += 1;
+        }
+        self.last = Some((document_id, field));
+        Ok(())
+    }
+
+    /// 위치를 다시 인코딩하지 않고 Hit를 더한다.
+    pub fn push(&mut self, hit: RawHit) -> Result<(), Error> {
+        self.push_key(hit.document_id, hit.field)?;
+        self.buf.extend_from_slice(hit.positions);
+        Ok(())
+    }
+
+    /// 문서 하나의 필드 하나에서 단어가 나온 위치를 더한다. 위치는 정렬되어 있어야 한다.
+    pub fn push_positions(
+        &mut self,
+        document_id: u32,
+        field: Field,
+        positions: &[u32],
+    ) -> Result<(), Error> {
+        self.push_key(document_id, field)?;
+        write_varint(&mut self.buf, positions.len() as u64);
+        positions.iter().fold(0, |last, &position| {
+            write_varint(&mut self.buf, (position - last) as u64);
+            position
+        });
+        Ok(())
+    }
+
+    /// 지금까지 넣은 문서의 수. 필드가 여러 개인 문서도 한 번만 센다.
+    pub fn df(&self) -> u32 {
+        self.df
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Posting 목록을 문서와 필드 단위로 나눈다. 위치는 디코딩하지 않는다.
+pub fn split_hits(mut buf: &[u8]) -> Result<Vec<RawHit<'_>>, Error> {
+    let mut hits: Vec<RawHit> = Vec::new();
+    let mut document_id = 0u32;
+    while !buf.is_empty() {
+        document_id = document_id
+            .checked_add(read_varint_u32(&mut buf)?)
+            .ok_or(FormatError::BadPostings("document id overflow"))?;
+        let field = Field::from_id(read_varint_u32(&mut buf)?)
+            .ok_or(FormatError::BadPostings("unknown field"))?;
+        if hits
+            .last()
+            .is_some_and(|last| (last.document_id, last.field) >= (document_id, field))
+        {
+            return Err(FormatError::BadPostings("document ids are out of order").into());
+        }
+        let start = buf;
+        let positions_len = read_varint(&mut buf)? as usize;
+        skip_varints(&mut buf, positions_len)?;
+        hits.push(RawHit {
+            document_id,
+            field,
+            positions: &start[..start.len() - buf.len()],
+        });
+    }
+    Ok(hits)
+}
+
+/// 단어 하나의 인덱스 데이터를 Posting 목록으로 디코딩한다.
+/// 한 문서의 여러 필드는 Posting 하나로 합친다.
+pub fn decode_hits(buf: &[u8]) -> Result<Vec<Posting>, Error> {
+    decode_hits_in(buf, None)
+}
+
+/// `decode_hits`와 같지만 field가 있으면 그 필드의 위치만 남긴다.
+pub fn decode_hits_in(buf: &[u8], field: Option<Field>) -> Result<Vec<Posting>, Error> {
+    split_hits(buf)?
+        .into_iter()
+        .filter(|hit| field.is_none_or(|field| hit.field == field))
+        .try_fold(Vec::<Posting>::new(), |mut postings, hit| {
+            let mut buf = hit.positions;
+            let positions_len = read_varint(&mut buf)? as usize;
+            let mut position = 0u32;
+            let positions = (0..positions_len)
+                .map(|_| {
+                    position = position.wrapping_add(read_varint_u32(&mut buf)?);
+                    Ok(position)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            // 필드의 위치는 번호 순으로 커지므로 이어 붙여도 정렬되어 있다.
+            match postings.last_mut() {
+                Some(last) if last.document_id == hit.document_id => {
+                    last.positions.extend(positions)
+                }
+                _ => postings.push(Posting {
+                    document_id: hit.document_id,
+                    positions,
+                }),
+            }
+            Ok(postings)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Pipeline;
+
+    #[test]
+    fn test_posting_list_round_trip() {
+        let hits: [(u32, Field, &[u32]); 3] = [
+            (3, Field::Body, &[0, 7, 300]),
+            (3, Field::Title, &[402]),
+            (1000, Field::Tags, &[5]),
+        ];
+        let mut builder = PostingListBuilder::new();
+        hits.iter()
+            .try_for_each(|&(document_id, field, positions)| {
+                builder.push_positions(document_id, field, positions)
+            })
+            .unwrap();
+        assert_eq!(builder.df(), 2);
+        let bytes = builder.into_bytes();
+        let posting = |document_id, positions: &[u32]| Posting {
+            document_id,
+            positions: positions.to_vec(),
+        };
+        assert_eq!(
+            decode_hits(&bytes).unwrap(),
+            [posting(3, &[0, 7, 300, 402]), posting(1000, &[5])]
+        );
+        assert_eq!(
+            decode_hits_in(&bytes, Some(Field::Title)).unwrap(),
+            [posting(3, &[402])]
+        );
+
+        let mut rebuilt = PostingListBuilder::new();
+        split_hits(&bytes)
+            .unwrap()
+            .into_iter()
+            .try_for_each(|hit| rebuilt.push(hit))
+            .unwrap();
+        assert_eq!(rebuilt.into_bytes(), bytes);
+        assert!(push_out_of_order().is_err());
+    }
+
+    fn push_out_of_order() -> Result<(), Error> {
+        let mut builder = PostingListBuilder::new();
+        builder.push_positions(2, Field::Title, &[])?;
+        builder.push_positions(2, Field::Body, &[])
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let analyzer = Pipeline::default();
+        let mut index = InMemoryIndex::from_single_document(1, "gap buffer".into(), &analyzer);
+        index.merge(InMemoryIndex::from_single_document(
+            2,
+            "gap rope".into(),
+            &analyzer,
+        ));
+        let expected = index
+            .map
+            .iter()
+            .map(|(term, hits)| term_bytes(term) + hits.iter().map(hit_bytes).sum::<usize>())
+            .sum::<usize>();
+        assert_eq!(index.bytes(), expected);
+        assert!(index.is_large(expected - 1));
+        assert!(!index.is_large(expected));
+    }
 }
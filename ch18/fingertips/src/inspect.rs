@@ -0,0 +1,260 @@
+use std::{fmt, fs::File, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{
+    error::{Error, PathContext},
+    header::{FormatError, Header, Section},
+    index::decode_hits,
+    read::{Entry, IndexFileReader},
+};
+
+/// 인덱스 파일의 헤더와 테이블
+/// 체크섬을 확인하지 않고 읽으므로 손상된 파일도 들여다볼 수 있다. 확인은 `verify`로 한다.
+pub struct IndexFileInfo {
+    mmap: Mmap,
+    header: Header,
+    /// 파일에 적힌 순서대로 읽은 테이블
+    entries: Vec<Entry>,
+}
+
+/// Posting 목록 크기의 분포(바이트)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostingSizes {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+    pub total: u64,
+}
+
+/// `IndexFileInfo::verify`가 찾은 문제
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// 문제가 있는 엔트리의 단어. 파일 전체의 문제는 None
+    pub term: Option<String>,
+    pub reason: FormatError,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.term {
+            Some(term) => write!(f, "{:?}: {}", term, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl IndexFileInfo {
+    /// 인덱스 파일을 매핑하고 헤더와 테이블을 읽는다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexFileInfo, Error> {
+        IndexFileInfo::open_at(filename.as_ref()).with_path(filename)
+    }
+
+    fn open_at(filename: &Path) -> Result<IndexFileInfo, Error> {
+        let file = File::open(filename)?;
+        // 검색과 마찬가지로 인덱스 파일은 제자리에서 바뀌지 않는다.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header = Header::read(&mut &mmap[..])?;
+        if header.contents_offset < header.size() || header.contents_offset > mmap.len() as u64 {
+            return Err(FormatError::Truncated {
+                expected: header.contents_offset.max(header.size()),
+                actual: mmap.len() as u64,
+            }
+            .into());
+        }
+
+        let mut contents = &mmap[header.contents_offset as usize..];
+        let mut entries = Vec::new();
+        while let Some(entry) = IndexFileReader::read_entry(&mut contents)? {
+            entries.push(entry);
+        }
+        Ok(IndexFileInfo {
+            mmap,
+            header,
+            entries,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// 파일 전체의 바이트 수
+    pub fn file_len(&self) -> u64 {
+        self.mmap.len() as u64
+    }
+
+    /// 파일에 적힌 순서대로 읽은 테이블
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// 테이블의 바이트 수
+    pub fn contents_len(&self) -> u64 {
+        self.file_len() - self.header.contents_offset
+    }
+
+    /// 문서 수가 많은 순으로 n개의 엔트리. 문서 수가 같으면 단어 순이다.
+    pub fn top_terms(&self, n: usize) -> Vec<&Entry> {
+        let mut top = self.entries.iter().collect::<Vec<_>>();
+        top.sort_by(|a, b| b.df.cmp(&a.df).then_with(|| a.term.cmp(&b.term)));
+        top.truncate(n);
+        top
+    }
+
+    /// Posting 목록 크기의 분포. 엔트리가 없으면 None
+    pub fn posting_sizes(&self) -> Option<PostingSizes> {
+        let mut sizes = self
+            .entries
+            .iter()
+            .map(|entry| entry.nbytes)
+            .collect::<Vec<_>>();
+        sizes.sort_unstable();
+        Some(PostingSizes {
+            min: *sizes.first()?,
+            median: sizes[sizes.len() / 2],
+            max: *sizes.last()?,
+            total: sizes.iter().sum(),
+        })
+    }
+
+    /// 파일 전체를 확인해서 찾은 문제를 모두 돌려준다. 문제가 없으면 빈 목록이다.
+    /// 체크섬과 단어 수, 단어의 순서를 확인하고, 엔트리마다 인덱스 데이터가 앞 엔트리에 바로 이어지고
+    /// 테이블 앞에서 끝나는지, Posting 목록을 디코딩할 수 있고 문서 수가 df와 같은지 확인한다.
+    pub fn verify(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let mut problem = |term: Option<&str>, reason: FormatError| {
+            problems.push(Problem {
+                term: term.map(str::to_string),
+                reason,
+            })
+        };
+
+        let data_start = self.header.size();
+        let contents_offset = self.header.contents_offset;
+        if crc32fast::hash(&self.mmap[data_start as usize..contents_offset as usize])
+            != self.header.main_crc
+        {
+            problem(None, FormatError::ChecksumMismatch(Section::Main));
+        }
+        if crc32fast::hash(&self.mmap[contents_offset as usize..]) != self.header.contents_crc {
+            problem(None, FormatError::ChecksumMismatch(Section::Contents));
+        }
+        if self.entries.len() as u64 != self.header.term_count {
+            problem(
+                None,
+                FormatError::TermCountMismatch {
+                    expected: self.header.term_count,
+                    actual: self.entries.len() as u64,
+                },
+            );
+        }
+        if self
+            .entries
+            .windows(2)
+            .any(|pair| pair[0].term >= pair[1].term)
+        {
+            problem(None, FormatError::UnsortedTerms);
+        }
+
+        let end = self.entries.iter().fold(data_start, |expected, entry| {
+            let term = Some(entry.term.as_str());
+            if entry.offset != expected {
+                problem(
+                    term,
+                    FormatError::NotContiguous {
+                        expected,
+                        actual: entry.offset,
+                    },
+                );
+            }
+            let entry_end = entry.offset.saturating_add(entry.nbytes);
+            if entry.offset < data_start || entry_end > contents_offset {
+                problem(
+                    term,
+                    FormatError::Truncated {
+                        expected: entry_end,
+                        actual: contents_offset,
+                    },
+                );
+                return entry_end;
+            }
+
+            match decode_hits(&self.mmap[entry.offset as usize..entry_end as usize]) {
+                Ok(postings) if postings.len() != entry.df as usize => problem(
+                    term,
+                    FormatError::DfMismatch {
+                        expected: entry.df,
+                        actual: postings.len() as u32,
+                    },
+                ),
+                Ok(_) => {}
+                Err(Error::CorruptIndex { reason, .. }) => problem(term, reason),
+                Err(_) => problem(term, FormatError::BadPostings("cannot decode posting list")),
+            }
+            entry_end
+        });
+        if end != contents_offset {
+            problem(
+                None,
+                FormatError::NotContiguous {
+                    expected: end,
+                    actual: contents_offset,
+                },
+            );
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        analysis::Pipeline,
+        test_util::{build_index, TestDir},
+    };
+
+    #[test]
+    fn test_verify() {
+        let dir = TestDir::new("inspect");
+        let path = build_index(
+            &dir,
+            &Pipeline::default(),
+            &[("a.txt", "gap buffer gap"), ("b.txt", "rope buffer")],
+        );
+
+        let info = IndexFileInfo::open(&path).unwrap();
+        assert_eq!(info.entries().len(), 3);
+        assert!(info.verify().is_empty());
+        let top = info.top_terms(1);
+        assert_eq!((top[0].term.as_str(), top[0].df), ("buffer", 2));
+
+        // "buffer" 엔트리의 df를 바꾼다.
+        let mut bytes = fs::read(&path).unwrap();
+        let df_at = info.header().contents_offset as usize + 16;
+        drop(info);
+        bytes[df_at] = 7;
+        fs::write(&path, bytes).unwrap();
+        let problems = IndexFileInfo::open(&path).unwrap().verify();
+        assert_eq!(
+            problems,
+            [
+                Problem {
+                    term: None,
+                    reason: FormatError::ChecksumMismatch(Section::Contents),
+                },
+                Problem {
+                    term: Some("buffer".to_string()),
+                    reason: FormatError::DfMismatch {
+                        expected: 7,
+                        actual: 2,
+                    },
+                },
+            ]
+        );
+    }
+}
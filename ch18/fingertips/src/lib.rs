@@ -1,8 +1,27 @@
+pub mod analysis;
+pub mod documents;
+pub mod error;
+pub mod extract;
+pub mod field;
+pub mod header;
+pub mod highlight;
 pub mod index;
+pub mod inspect;
 pub mod merge;
 pub mod off_thread;
 pub mod parse_args;
+pub mod progress;
+pub mod query;
+pub mod rank;
 pub mod read;
 pub mod run;
+pub mod search;
+pub mod server;
+pub mod terms;
+#[cfg(test)]
+mod test_util;
 pub mod tmp;
+pub mod tombstone;
+pub mod varint;
+pub mod walk;
 pub mod write;
@@ -1,9 +1,26 @@
+use std::sync::Arc;
+
 use fingertips::{
-    parse_args::{self},
+    parse_args::{self, Command},
+    progress::ProgressBar,
     run::{self},
 };
 
 fn main() {
-    let args = parse_args::parse_args();
-    run::run(args).unwrap_or_else(|e| println!("error: {}", e));
+    let result = match parse_args::parse_args() {
+        Command::Index(args) => {
+            // 실패해도 진행 막대는 지운다.
+            let progress = Arc::new(ProgressBar::new());
+            let result = run::run(args, progress.clone());
+            let stats = progress.finish();
+            result.map(|()| println!("{}", serde_json::to_string(&stats).unwrap()))
+        }
+        Command::Search(args) => run::search(args),
+        Command::Delete(args) => run::delete(args),
+        Command::Inspect(args) => run::inspect(args),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(e.exit_code());
+    }
 }
@@ -1,129 +1,296 @@
 use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
     fs::{self, File},
     io::{self, BufWriter},
     mem,
     path::{Path, PathBuf},
 };
 
-use crate::{read::IndexFileReader, tmp::TmpDir, write::IndexFileWriter};
+use crate::{
+    analysis::Pipeline,
+    error::{Error, PathContext},
+    header::{FormatError, Header},
+    index::{split_hits, PostingListBuilder},
+    progress::{Event, SharedProgress},
+    read::IndexFileReader,
+    terms::{terms_path, TermDictionaryBuilder},
+    tmp::TmpDir,
+    tombstone::Tombstones,
+    write::IndexFileWriter,
+};
 
 pub struct FileMerge {
-    output_dir: PathBuf,
+    /// 병합한 인덱스 파일의 경로
+    output: PathBuf,
     tmp_dir: TmpDir,
     stacks: Vec<Vec<PathBuf>>,
+    /// 병합하면서 빼낼 문서
+    tombstones: Tombstones,
+    /// 병합에 넣은 기존 인덱스. 병합한 뒤에도 지우지 않는다.
+    existing: Option<PathBuf>,
+    /// 한번에 병합할 파일 수
+    fan_in: usize,
+    progress: SharedProgress,
 }
 
-/// 한번에 병합할 파일 수
-const NSTREAMS: usize = 8;
+/// 한번에 병합할 파일 수의 기본값
+pub const DEFAULT_FAN_IN: usize = 8;
 
-const MERGED_FILENAME: &str = "index.dat";
+/// 인덱스 파일의 기본 이름
+pub const MERGED_FILENAME: &str = "index.dat";
 
 impl FileMerge {
-    pub fn new(output_dir: &Path) -> Self {
+    /// output은 병합한 인덱스 파일의 경로, tmp_dir은 중간 파일을 만들 곳이다.
+    /// fan_in개의 파일이 모일 때마다 하나로 병합한다. 2보다 작으면 2를 쓴다.
+    /// 병합할 때마다 progress에 `Event::Merged`를, 끝나면 `Event::Finished`를 보낸다.
+    pub fn new(output: &Path, tmp_dir: TmpDir, fan_in: usize, progress: SharedProgress) -> Self {
         Self {
-            output_dir: output_dir.to_owned(),
-            tmp_dir: TmpDir::new(output_dir),
+            output: output.to_owned(),
+            tmp_dir,
             stacks: Vec::new(),
+            tombstones: Tombstones::new(),
+            existing: None,
+            fan_in: fan_in.max(2),
+            progress,
         }
     }
 
-    pub fn add_file(&mut self, mut file: PathBuf) -> Result<(), io::Error> {
+    /// 기존 인덱스 파일을 병합 대상에 넣는다.
+    /// 복사하지 않고 제자리에서 읽으며, 다른 병합 대상과 달리 병합한 뒤에도 지우지 않는다.
+    /// 새 문서의 번호가 더 크므로 다른 파일보다 먼저 넣어야 한다.
+    /// tombstones의 문서는 병합하면서 인덱스에서 지운다.
+    pub fn add_existing_index(&mut self, tombstones: Tombstones) -> Result<(), Error> {
+        self.tombstones = tombstones;
+        self.existing = Some(self.output.clone());
+        self.add_file(self.output.clone())
+    }
+
+    pub fn add_file(&mut self, mut file: PathBuf) -> Result<(), Error> {
         let mut level = 0;
         loop {
             if level == self.stacks.len() {
                 self.stacks.push(Vec::new());
             }
             self.stacks[level].push(file);
-            if self.stacks[level].len() < NSTREAMS {
+            if self.stacks[level].len() < self.fan_in {
                 break Ok(());
             }
             let (filename, out) = self.tmp_dir.create()?;
             let mut to_merge = Vec::new();
             mem::swap(&mut self.stacks[level], &mut to_merge);
-            merge_stream(to_merge, out)?;
+            let files = to_merge.len();
+            let bytes = merge_stream(
+                to_merge,
+                &filename,
+                out,
+                &self.tombstones,
+                self.existing.as_deref(),
+                None,
+            )?;
             file = filename;
             level += 1;
+            self.progress.event(Event::Merged {
+                level,
+                files,
+                bytes,
+            });
         }
     }
 
-    pub fn finish(mut self) -> Result<(), io::Error> {
-        let mut tmp = Vec::with_capacity(NSTREAMS);
-        self.stacks.into_iter().try_for_each(|statck| {
-            statck.into_iter().rev().try_for_each(|file| {
-                tmp.push(file);
-                if tmp.len() == NSTREAMS {
-                    return merge_reversed(&mut tmp, &mut self.tmp_dir);
-                }
-                Ok(())
-            })
+    /// 남은 파일을 모두 병합해서 output으로 옮기고, 옆에 단어 사전 파일을 만든다.
+    /// 단어 사전은 마지막 병합에서 테이블을 쓰면서 함께 만들고, output을 바꾸기 전에 쓴다.
+    pub fn finish(mut self) -> Result<(), Error> {
+        // 남은 파일은 가장 높은 단계보다 한 단계 위에서 병합한다.
+        let level = self.stacks.len() + 1;
+        let mut merge = |tmp: &mut Vec<PathBuf>, terms: Option<&mut TermDictionaryBuilder>| {
+            let files = tmp.len();
+            let bytes = merge_reversed(
+                tmp,
+                &mut self.tmp_dir,
+                &self.tombstones,
+                self.existing.as_deref(),
+                terms,
+            )?;
+            self.progress.event(Event::Merged {
+                level,
+                files,
+                bytes,
+            });
+            <Result<(), Error>>::Ok(())
+        };
+        let files = self
+            .stacks
+            .into_iter()
+            .flat_map(|stack| stack.into_iter().rev())
+            .collect::<Vec<_>>();
+        let count = files.len();
+        let mut tmp = Vec::with_capacity(self.fan_in);
+        files.into_iter().enumerate().try_for_each(|(i, file)| {
+            tmp.push(file);
+            // 마지막 병합은 단어 사전을 함께 만들어야 하므로 남겨둔다.
+            if tmp.len() == self.fan_in && i + 1 < count {
+                return merge(&mut tmp, None);
+            }
+            Ok(())
         })?;
 
-        if tmp.len() > 1 {
-            merge_reversed(&mut tmp, &mut self.tmp_dir)?;
+        // 기존 인덱스만 남았고 지울 문서도 없으면 인덱스와 사전을 그대로 둔다.
+        let unchanged = tmp.len() == 1 && self.tombstones.is_empty();
+        if !(unchanged && tmp.first() == self.existing.as_ref()) {
+            if tmp.is_empty() {
+                return Err(Error::EmptyCorpus);
+            }
+            let mut terms = TermDictionaryBuilder::new();
+            merge(&mut tmp, Some(&mut terms))?;
+            assert_eq!(tmp.len(), 1);
+
+            // 사전을 먼저 쓴다. 옮기다 실패해도 사전의 체크섬이 기존 인덱스와 맞지 않으므로 쓰이지 않는다.
+            let last_file = tmp.pop().unwrap();
+            let mut f = File::open(&last_file).with_path(&last_file)?;
+            let header = Header::read(&mut f).with_path(&last_file)?;
+            terms
+                .finish()
+                .with_path(&last_file)?
+                .write(terms_path(&self.output), header.contents_crc)?;
+            move_file(&last_file, &self.output)?;
         }
-        assert!(tmp.len() <= 1);
-
-        match tmp.pop() {
-            Some(last_file) => fs::rename(last_file, self.output_dir.join(MERGED_FILENAME)),
-            None => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "no documents were parsed or none contained any words",
-            )),
+
+        self.progress.event(Event::Finished {
+            path: self.output.clone(),
+            bytes: fs::metadata(&self.output).with_path(&self.output)?.len(),
+        });
+        Ok(())
+    }
+}
+
+/// 파일을 옮긴다. 임시 디렉터리가 다른 파일 시스템에 있으면 복사한 뒤에 지운다.
+/// 검색하는 쪽은 인덱스 파일을 매핑해서 읽으므로 to를 제자리에서 덮어쓰면 안 된다.
+/// 복사할 때도 to 옆의 임시 파일에 복사한 뒤에 이름을 바꿔서 교체한다.
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut staged = to.as_os_str().to_owned();
+            staged.push(".tmp");
+            let staged = PathBuf::from(staged);
+            let result = fs::copy(from, &staged)
+                .with_path(&staged)
+                .and_then(|_| fs::rename(&staged, to).with_path(to));
+            if result.is_err() {
+                let _ = fs::remove_file(&staged);
+            }
+            result?;
+            fs::remove_file(from).with_path(from)
         }
+        result => result.with_path(to),
     }
 }
 
-fn merge_stream(files: Vec<PathBuf>, out: BufWriter<File>) -> Result<(), io::Error> {
+/// 여러 인덱스 파일을 하나로 합친다.
+/// tombstones에 있는 문서의 Hit는 버리고, Hit가 하나도 남지 않은 단어는 테이블에서 뺀다.
+/// 문서 번호를 앞 문서와의 차이로 쓰므로 Posting 목록은 이어 붙이지 않고 다시 만든다.
+/// 여러 스레드가 만든 파일은 문서 번호가 서로 섞여 있으므로 모든 파일의 Hit를 (문서 번호, 필드) 순으로 합친다.
+/// 분석기가 다른 파일은 단어가 맞지 않으므로 합치지 않는다.
+/// out은 out_path에 쓴다. 병합한 파일은 지우지만 existing은 남겨둔다. 병합한 파일의 바이트 수를 돌려준다.
+/// terms를 주면 테이블에 쓰는 단어를 순서대로 넣는다.
+pub fn merge_stream(
+    files: Vec<PathBuf>,
+    out_path: &Path,
+    out: BufWriter<File>,
+    tombstones: &Tombstones,
+    existing: Option<&Path>,
+    mut terms: Option<&mut TermDictionaryBuilder>,
+) -> Result<u64, Error> {
     let mut streams = files
         .into_iter()
-        .map(IndexFileReader::open)
+        .map(|file| match existing {
+            Some(existing) if file == existing => IndexFileReader::open_kept(file),
+            _ => IndexFileReader::open(file),
+        })
         .collect::<Result<Vec<_>, _>>()?;
 
-    let mut output = IndexFileWriter::new(out)?;
-
-    let mut point = 0;
-    let mut count = streams.iter().filter(|s| s.peek().is_some()).count();
-    while count > 0 {
-        let mut term = None;
-        let mut nbytes = 0;
-        let mut df = 0;
-        streams.iter().filter_map(|s| s.peek()).for_each(|entry| {
-            if term.is_none() || entry.term < *term.as_ref().unwrap() {
-                term = Some(entry.term.clone());
-                nbytes = entry.nbytes;
-                df = entry.df;
-            } else if entry.term == *term.as_ref().unwrap() {
-                nbytes += entry.nbytes;
-                df += entry.df;
-            }
-        });
-        let term = term.expect("bug in algorithm!");
-
-        streams
-            .iter_mut()
-            .filter(|s| s.is_at(&term))
-            .try_for_each(|s| {
-                s.move_entry_to(&mut output)?;
-                if s.peek().is_none() {
-                    count -= 1;
+    // 설정 문자열을 정규화하기 전에 만든 인덱스도 있으므로 정규화해서 비교한다.
+    let specs = streams
+        .iter()
+        .map(|s| {
+            Pipeline::from_spec(s.analyzer())
+                .map_or_else(|_| s.analyzer().to_string(), |p| p.spec().to_string())
+        })
+        .collect::<Vec<_>>();
+    let analyzer = specs.first().cloned().unwrap_or_default();
+    if let Some(other) = specs.iter().find(|spec| **spec != analyzer) {
+        return Err(FormatError::AnalyzerMismatch {
+            expected: analyzer,
+            actual: other.clone(),
+        }
+        .into());
+    }
+    let mut output = IndexFileWriter::new(out, &analyzer).with_path(out_path)?;
+
+    // 각 파일의 다음 단어를 힙에 넣어두고 가장 작은 단어부터 꺼낸다.
+    let mut heap = streams
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| s.peek().map(|entry| Reverse((entry.term.clone(), i))))
+        .collect::<BinaryHeap<_>>();
+    while let Some(Reverse((term, first))) = heap.pop() {
+        let mut at_term = vec![first];
+        while heap.peek().is_some_and(|Reverse((next, _))| *next == term) {
+            at_term.extend(heap.pop().map(|Reverse((_, i))| i));
+        }
+
+        let buffers = at_term
+            .into_iter()
+            .map(|i| {
+                let s = &mut streams[i];
+                let hits = s.take_hits()?;
+                if let Some(entry) = s.peek() {
+                    heap.push(Reverse((entry.term.clone(), i)));
                 }
-                <Result<(), io::Error>>::Ok(())
-            })?;
+                Ok((i, hits))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut hits = buffers
+            .iter()
+            .map(|(i, buf)| split_hits(buf).with_path(streams[*i].filename()))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        hits.sort_by_key(|hit| (hit.document_id, hit.field));
 
-        output.write_content_entry(term, df, point, nbytes);
-        point += nbytes;
+        let mut postings = PostingListBuilder::new();
+        hits.into_iter()
+            .filter(|hit| !tombstones.contains(hit.document_id))
+            .try_for_each(|hit| postings.push(hit))?;
+
+        let df = postings.df();
+        if df > 0 {
+            if let Some(terms) = terms.as_deref_mut() {
+                terms.insert(&term).with_path(out_path)?;
+            }
+            let point = output.offset();
+            output
+                .write_main(&postings.into_bytes())
+                .with_path(out_path)?;
+            output.write_content_entry(term, df, point, output.offset() - point);
+        }
     }
 
     assert!(streams.iter().all(|s| s.peek().is_none()));
-    output.finish()
+    output.finish().with_path(out_path)
 }
 
-fn merge_reversed(filenames: &mut Vec<PathBuf>, tmp_dir: &mut TmpDir) -> io::Result<()> {
+fn merge_reversed(
+    filenames: &mut Vec<PathBuf>,
+    tmp_dir: &mut TmpDir,
+    tombstones: &Tombstones,
+    existing: Option<&Path>,
+    terms: Option<&mut TermDictionaryBuilder>,
+) -> Result<u64, Error> {
     filenames.reverse();
     let (merged_filename, out) = tmp_dir.create()?;
-    let mut to_merge = Vec::with_capacity(NSTREAMS);
+    let mut to_merge = Vec::with_capacity(filenames.len());
     mem::swap(filenames, &mut to_merge);
-    merge_stream(to_merge, out)?;
+    let bytes = merge_stream(to_merge, &merged_filename, out, tombstones, existing, terms)?;
     filenames.push(merged_filename);
-    Ok(())
+    Ok(bytes)
 }
@@ -1,9 +1,40 @@
-use clap::{Arg, ArgAction, Command};
+use std::{ffi::OsString, thread};
+
+use clap::{Arg, ArgAction};
+
+use crate::{extract::InvalidUtf8, index::DEFAULT_MEMORY_LIMIT, merge::DEFAULT_FAN_IN};
+
+/// 실행할 명령
+#[derive(Debug)]
+pub enum Command {
+    /// 역색인을 만든다.
+    Index(Args),
+    /// 역색인에서 단어를 찾는다.
+    Search(SearchArgs),
+    /// 문서를 지운 것으로 표시한다.
+    Delete(DeleteArgs),
+    /// 인덱스 파일의 내용을 요약하거나 확인한다.
+    Inspect(InspectArgs),
+}
 
 /// 인수 목록
 #[derive(Debug)]
 pub struct Args {
     single_threaded: bool,
+    jobs: usize,
+    memory_limit: usize,
+    merge_fan_in: usize,
+    append: bool,
+    analyzer: Option<String>,
+    output: String,
+    name: String,
+    tmp_dir: Option<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    no_ignore: bool,
+    follow_symlinks: bool,
+    max_file_size: Option<u64>,
+    invalid_utf8: InvalidUtf8,
     filename: Vec<String>,
 }
 
@@ -12,16 +43,201 @@ impl Args {
         self.single_threaded
     }
 
+    /// 파이프라인의 각 단계를 실행할 스레드 수
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// 메모리에 모을 인덱스의 최대 바이트 수. 넘으면 임시 파일로 쓴다.
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// 임시 파일을 한번에 병합할 개수
+    pub fn merge_fan_in(&self) -> usize {
+        self.merge_fan_in
+    }
+
+    /// 기존 인덱스에 문서를 더한다.
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    /// 지정한 분석기 설정. 지정하지 않으면 기존 인덱스나 기본값을 따른다.
+    pub fn analyzer(&self) -> Option<&str> {
+        self.analyzer.as_deref()
+    }
+
+    /// 인덱스와 문서 테이블을 둘 디렉터리
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// 인덱스 파일의 이름
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 임시 파일을 만들 디렉터리. 지정하지 않으면 output을 쓴다.
+    pub fn tmp_dir(&self) -> &str {
+        self.tmp_dir.as_deref().unwrap_or(&self.output)
+    }
+
+    /// 디렉터리에서 색인할 파일의 glob. 비어 있으면 `walk::default_includes`를 쓴다.
+    pub fn include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// 디렉터리에서 건너뛸 파일이나 디렉터리의 glob
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// .gitignore 같은 무시 파일과 숨은 파일을 가리지 않는다.
+    pub fn is_no_ignore(&self) -> bool {
+        self.no_ignore
+    }
+
+    /// 디렉터리 안의 심볼릭 링크를 따라간다.
+    pub fn is_follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// 이보다 큰 파일은 색인하지 않는다.
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.max_file_size
+    }
+
+    /// UTF-8이 아닌 문서를 어떻게 할지
+    pub fn invalid_utf8(&self) -> InvalidUtf8 {
+        self.invalid_utf8
+    }
+
     pub fn filename(&self) -> &[String] {
         &self.filename
     }
 }
 
-/// 커맨드라인에서 Args를 생성한다.
-pub fn parse_args() -> Args {
-    let matches = Command::new("fingertips")
+/// search 명령의 인수 목록
+#[derive(Debug)]
+pub struct SearchArgs {
+    index: String,
+    query: String,
+    top: Option<usize>,
+    snippets: bool,
+}
+
+impl SearchArgs {
+    pub fn index(&self) -> &str {
+        &self.index
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// 지정한 경우 BM25 점수가 높은 문서만 출력한다.
+    pub fn top(&self) -> Option<usize> {
+        self.top
+    }
+
+    /// 원문을 다시 읽어서 맞은 단어 주변을 출력한다.
+    pub fn is_snippets(&self) -> bool {
+        self.snippets
+    }
+}
+
+/// delete 명령의 인수 목록
+#[derive(Debug)]
+pub struct DeleteArgs {
+    index: String,
+    paths: Vec<String>,
+}
+
+impl DeleteArgs {
+    pub fn index(&self) -> &str {
+        &self.index
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+}
+
+/// 기본으로 출력할 문서 수가 많은 단어의 수
+pub const DEFAULT_INSPECT_TOP: usize = 10;
+
+/// inspect 명령의 인수 목록
+#[derive(Debug)]
+pub struct InspectArgs {
+    index: String,
+    top: usize,
+    verify: bool,
+}
+
+impl InspectArgs {
+    pub fn index(&self) -> &str {
+        &self.index
+    }
+
+    /// 출력할 문서 수가 많은 단어의 수
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    /// 요약 대신 파일 전체를 확인한다.
+    pub fn is_verify(&self) -> bool {
+        self.verify
+    }
+}
+
+/// serve 프로그램의 인수 목록
+#[derive(Debug)]
+pub struct ServeArgs {
+    dir: String,
+    name: String,
+    addr: String,
+    reload_interval: u64,
+}
+
+impl ServeArgs {
+    /// 인덱스 파일과 문서 테이블이 있는 디렉터리
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    /// 인덱스 파일의 이름
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 서버 주소
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// 인덱스가 바뀌었는지 확인하는 간격(초). 0이면 다시 읽지 않는다.
+    pub fn reload_interval(&self) -> u64 {
+        self.reload_interval
+    }
+}
+
+/// 커맨드라인에서 Command를 생성한다.
+pub fn parse_args() -> Command {
+    parse_args_from(std::env::args_os())
+}
+
+/// 주어진 인수 목록을 해석한다. 첫 인수는 프로그램 이름이다.
+pub fn parse_args_from<I, T>(args: I) -> Command
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = clap::Command::new("fingertips")
         .about("문서의 역색인을 만든다.")
         .version("0.1.0")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("single_threaded")
                 .help("싱글스레드로 작업을 실행한다.")
@@ -29,21 +245,343 @@ pub fn parse_args() -> Args {
                 .short('1')
                 .long("single-threaded"),
         )
+        .arg(
+            Arg::new("jobs")
+                .help(
+                    "파일 읽기, 색인, 메모리 병합 단계를 각각 실행할 스레드 수. \
+                기본값은 CPU 수이다. 스레드 수와 관계없이 문서 번호는 같다.",
+                )
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("memory_limit")
+                .help(
+                    "메모리에 모을 인덱스의 대략적인 크기. 넘으면 임시 파일로 쓴다. \
+                K, M, G 단위를 붙일 수 있다. 기본값은 512M 이다.",
+                )
+                .long("memory-limit")
+                .value_name("BYTES")
+                .value_parser(parse_size),
+        )
+        .arg(
+            Arg::new("merge_fan_in")
+                .help("임시 파일을 한번에 병합할 개수. 기본값은 8이다.")
+                .long("merge-fan-in")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(2..)),
+        )
+        .arg(
+            Arg::new("append")
+                .help(
+                    "기존 인덱스를 다시 만들지 않고 새 문서를 더한다. \
+                바뀐 문서는 다시 색인하고 사라진 문서는 인덱스에서 지운다.",
+                )
+                .action(ArgAction::SetTrue)
+                .short('a')
+                .long("append"),
+        )
+        .arg(
+            Arg::new("analyzer")
+                .help(
+                    "단어를 나누는 분석기. lowercase, nfkc, cjk, stop, stem, ngram:N, ngram:MIN-MAX를 \
+                쉼표로 이어서 쓴다. cjk는 한중일 문자열을 두 글자씩 나눈다. \
+                기본값은 lowercase,cjk 이다. 설정은 인덱스에 기록되어 검색할 때도 같은 분석기를 쓴다.",
+                )
+                .long("analyzer")
+                .value_name("SPEC"),
+        )
+        .arg(
+            Arg::new("output")
+                .help("인덱스 파일과 문서 테이블을 쓸 디렉터리. 없으면 만든다.")
+                .short('o')
+                .long("output")
+                .value_name("DIR")
+                .default_value("."),
+        )
+        .arg(
+            Arg::new("name")
+                .help("인덱스 파일의 이름. 문서 테이블(.docs), 삭제 표시(.deleted), 지운 경로(.excluded), 단어 사전(.fst) 파일은 확장자만 바꾼 이름을 쓴다.")
+                .short('n')
+                .long("name")
+                .value_name("NAME")
+                .default_value("index.dat"),
+        )
+        .arg(
+            Arg::new("tmp_dir")
+                .help(
+                    "색인하는 동안 임시 파일을 만들 디렉터리. 지정하지 않으면 --output을 쓴다. \
+                실패하면 만든 임시 파일을 지운다.",
+                )
+                .long("tmp-dir")
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::new("include")
+                .help(
+                    "디렉터리에서 색인할 파일의 glob. 여러 번 쓸 수 있다. \
+                기본값은 .txt, .html, .htm, .xhtml, .md, .markdown, .jsonl, .ndjson 파일과 그 .gz 파일이다.",
+                )
+                .long("include")
+                .value_name("GLOB")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude")
+                .help("디렉터리에서 건너뛸 파일이나 디렉터리의 glob. 여러 번 쓸 수 있다.")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("no_ignore")
+                .help(".gitignore, .fingertipsignore 파일과 숨은 파일을 가리지 않고 색인한다.")
+                .action(ArgAction::SetTrue)
+                .long("no-ignore"),
+        )
+        .arg(
+            Arg::new("follow_symlinks")
+                .help("디렉터리 안의 심볼릭 링크를 따라간다. 주지 않으면 건너뛴다.")
+                .action(ArgAction::SetTrue)
+                .long("follow-symlinks"),
+        )
+        .arg(
+            Arg::new("max_file_size")
+                .help("이보다 큰 파일은 건너뛴다. K, M, G 단위를 붙일 수 있다. 예: 10M")
+                .long("max-file-size")
+                .value_name("BYTES")
+                .value_parser(parse_size),
+        )
+        .arg(
+            Arg::new("invalid_utf8")
+                .help(
+                    "UTF-8이 아닌 문서를 만났을 때 할 일. \
+                lossy는 읽을 수 없는 바이트를 바꿔서 색인하고, skip은 경고를 출력하고 건너뛴다.",
+                )
+                .long("invalid-utf8")
+                .value_name("POLICY")
+                .value_parser(["lossy", "skip"])
+                .default_value("lossy"),
+        )
         .arg(
             Arg::new("filenames")
                 .required(true)
                 .help(
                     "인덱스를 생성할 파일이나 디렉터리 이름. \
-                디렉터리를 지정한 경우에는 디렉터리 아래의 모든 .txt 파일을 인덱스 한다.",
+                디렉터리를 지정한 경우에는 하위 디렉터리까지 --include에 맞는 파일을 인덱스 한다. \
+                .html, .md 파일은 마크업을 빼고, .gz 파일은 압축을 풀어서 인덱스 한다. \
+                .jsonl, .ndjson 파일은 줄마다 title, body, tags 필드가 있는 문서 하나로 인덱스 한다.",
                 )
                 .num_args(1..),
         )
-        .get_matches();
+        .subcommand(
+            clap::Command::new("search")
+                .about("병합된 인덱스 파일에서 단어를 찾는다.")
+                .arg(
+                    Arg::new("index")
+                        .help("검색할 인덱스 파일")
+                        .short('i')
+                        .long("index")
+                        .default_value("index.dat"),
+                )
+                .arg(
+                    Arg::new("top")
+                        .help("BM25 점수가 높은 순으로 정렬해서 k개만 출력한다.")
+                        .short('k')
+                        .long("top")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("snippets")
+                        .help("원문을 다시 읽어서 맞은 단어 주변을 [단어]로 표시해서 출력한다.")
+                        .action(ArgAction::SetTrue)
+                        .short('s')
+                        .long("snippets"),
+                )
+                .arg(
+                    Arg::new("query")
+                        .required(true)
+                        .help(
+                            "찾을 단어나 질의. \
+                        예: rust AND (async OR tokio) NOT unsafe, \"gap buffer\", rust NEAR/5 async. \
+                        gap*은 접두어, g?p*r은 와일드카드, buffer~2는 편집 거리가 2 이하인 단어를 찾는다. \
+                        title:rust처럼 필드 이름(title, body, tags)을 붙이면 그 필드에서만 찾는다.",
+                        )
+                        .num_args(1..),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("delete")
+                .about(
+                    "문서를 지운 것으로 표시한다. 다음 --append 때 인덱스에서 지우고, \
+                    그 뒤의 --append도 지운 경로의 파일은 다시 색인하지 않는다.",
+                )
+                .arg(
+                    Arg::new("index")
+                        .help("문서를 지울 인덱스 파일")
+                        .short('i')
+                        .long("index")
+                        .default_value("index.dat"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .required(true)
+                        .help("지울 파일이나 디렉터리 이름")
+                        .num_args(1..),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("inspect")
+                .about("인덱스 파일의 헤더, 단어 수, 문서 수가 많은 단어, Posting 목록의 크기를 출력한다.")
+                .arg(
+                    Arg::new("index")
+                        .help("들여다볼 인덱스 파일")
+                        .short('i')
+                        .long("index")
+                        .default_value("index.dat"),
+                )
+                .arg(
+                    Arg::new("top")
+                        .help("문서 수가 많은 순으로 출력할 단어의 수. 기본값은 10이다.")
+                        .short('k')
+                        .long("top")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .help(
+                            "요약 대신 체크섬과 모든 엔트리를 확인한다. \
+                        인덱스 데이터가 빈틈없이 이어지고 파일 안에 있는지, df가 맞는지 본다. \
+                        문제가 있으면 모두 출력하고 실패한다.",
+                        )
+                        .action(ArgAction::SetTrue)
+                        .long("verify"),
+                ),
+        )
+        .get_matches_from(args);
 
-    let args = Args {
-        single_threaded: *matches.get_one("single_threaded").unwrap_or(&false),
-        filename: matches.get_many("filenames").unwrap().cloned().collect(),
+    match matches.subcommand() {
+        Some(("search", matches)) => Command::Search(SearchArgs {
+            index: matches.get_one::<String>("index").unwrap().clone(),
+            query: matches
+                .get_many::<String>("query")
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" "),
+            top: matches.get_one("top").copied(),
+            snippets: *matches.get_one("snippets").unwrap_or(&false),
+        }),
+        Some(("delete", matches)) => Command::Delete(DeleteArgs {
+            index: matches.get_one::<String>("index").unwrap().clone(),
+            paths: matches.get_many("paths").unwrap().cloned().collect(),
+        }),
+        Some(("inspect", matches)) => Command::Inspect(InspectArgs {
+            index: matches.get_one::<String>("index").unwrap().clone(),
+            top: matches
+                .get_one("top")
+                .copied()
+                .unwrap_or(DEFAULT_INSPECT_TOP),
+            verify: *matches.get_one("verify").unwrap_or(&false),
+        }),
+        _ => Command::Index(Args {
+            single_threaded: *matches.get_one("single_threaded").unwrap_or(&false),
+            jobs: matches
+                .get_one::<u64>("jobs")
+                .map(|&n| n as usize)
+                .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get())),
+            memory_limit: matches
+                .get_one::<u64>("memory_limit")
+                .map_or(DEFAULT_MEMORY_LIMIT, |&n| n as usize),
+            merge_fan_in: matches
+                .get_one::<u64>("merge_fan_in")
+                .map_or(DEFAULT_FAN_IN, |&n| n as usize),
+            append: *matches.get_one("append").unwrap_or(&false),
+            analyzer: matches.get_one::<String>("analyzer").cloned(),
+            output: matches.get_one::<String>("output").unwrap().clone(),
+            name: matches.get_one::<String>("name").unwrap().clone(),
+            tmp_dir: matches.get_one::<String>("tmp_dir").cloned(),
+            include: matches
+                .get_many("include")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+            exclude: matches
+                .get_many("exclude")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+            no_ignore: *matches.get_one("no_ignore").unwrap_or(&false),
+            follow_symlinks: *matches.get_one("follow_symlinks").unwrap_or(&false),
+            max_file_size: matches.get_one("max_file_size").copied(),
+            invalid_utf8: match matches.get_one::<String>("invalid_utf8").unwrap().as_str() {
+                "skip" => InvalidUtf8::Skip,
+                _ => InvalidUtf8::Lossy,
+            },
+            filename: matches.get_many("filenames").unwrap().cloned().collect(),
+        }),
+    }
+}
+
+/// 10M 같은 크기를 바이트 수로 바꾼다.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = match value.find(|ch: char| !ch.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, ""),
+    };
+    let unit = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("unknown size unit: {}", unit)),
     };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| format!("invalid size: {}", value))
+}
+
+/// serve 프로그램의 커맨드라인에서 ServeArgs를 생성한다.
+pub fn parse_serve_args() -> ServeArgs {
+    let matches = clap::Command::new("serve")
+        .about("인덱스 디렉터리를 HTTP로 검색한다. GET /search?q=질의&offset=0&limit=10")
+        .version("0.1.0")
+        .arg(
+            Arg::new("name")
+                .help("인덱스 파일의 이름")
+                .short('n')
+                .long("name")
+                .default_value("index.dat"),
+        )
+        .arg(
+            Arg::new("addr")
+                .help("서버 주소")
+                .long("addr")
+                .default_value("127.0.0.1:3000"),
+        )
+        .arg(
+            Arg::new("reload_interval")
+                .help("인덱스 파일이 바뀌었는지 확인하는 간격(초). 바뀌면 다시 읽는다. 0이면 확인하지 않는다.")
+                .long("reload-interval")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("2"),
+        )
+        .arg(
+            Arg::new("dir")
+                .help("인덱스 파일과 문서 테이블이 있는 디렉터리")
+                .default_value("."),
+        )
+        .get_matches();
 
-    args
+    ServeArgs {
+        dir: matches.get_one::<String>("dir").unwrap().clone(),
+        name: matches.get_one::<String>("name").unwrap().clone(),
+        addr: matches.get_one::<String>("addr").unwrap().clone(),
+        reload_interval: *matches.get_one("reload_interval").unwrap(),
+    }
 }
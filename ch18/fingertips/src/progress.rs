@@ -0,0 +1,259 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// 색인하는 동안 일어나는 일
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// 색인할 문서를 찾았다. 파일을 다 훑은 뒤에 파일 수를 한 번 보내고,
+    /// 레코드가 여럿인 파일을 읽으면 늘어난 문서 수를 더 보낸다.
+    Started { documents: usize },
+    /// 문서 하나를 색인했다.
+    DocumentIndexed {
+        document_id: u32,
+        /// 파일의 바이트 수
+        bytes: u64,
+        words: u64,
+    },
+    /// 읽을 수 없거나 너무 큰 문서를 건너뛰었다.
+    DocumentSkipped { path: PathBuf, reason: String },
+    /// 메모리의 인덱스를 임시 파일로 썼다.
+    TmpFileWritten { path: PathBuf, bytes: u64 },
+    /// 인덱스 파일 여러 개를 하나로 병합했다. level은 병합한 결과가 들어가는 단계이다.
+    Merged {
+        level: usize,
+        files: usize,
+        bytes: u64,
+    },
+    /// 인덱스 파일을 완성했다.
+    Finished { path: PathBuf, bytes: u64 },
+}
+
+/// 진행 상황을 받는 곳
+/// 파이프라인의 여러 스레드에서 부르므로 Send + Sync여야 한다.
+pub trait Progress: Send + Sync {
+    fn event(&self, event: Event);
+}
+
+/// 여러 스레드가 나눠 쓰는 Progress
+pub type SharedProgress = Arc<dyn Progress>;
+
+/// 이벤트를 버린다.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn event(&self, _event: Event) {}
+}
+
+/// 클로저로 이벤트를 받는다.
+impl<F: Fn(Event) + Send + Sync> Progress for F {
+    fn event(&self, event: Event) {
+        self(event)
+    }
+}
+
+/// 채널로 이벤트를 보낸다. 받는 쪽이 없어지면 버린다.
+impl Progress for Sender<Event> {
+    fn event(&self, event: Event) {
+        let _ = self.send(event);
+    }
+}
+
+/// 이벤트를 모은 통계
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// 색인할 문서 수
+    pub documents: usize,
+    pub documents_indexed: usize,
+    pub documents_skipped: usize,
+    /// 색인한 문서의 바이트 수
+    pub bytes_indexed: u64,
+    pub words_indexed: u64,
+    pub tmp_files_written: usize,
+    pub tmp_bytes_written: u64,
+    pub merges: usize,
+    /// 가장 높은 병합 단계
+    pub merge_levels: usize,
+    /// 완성한 인덱스 파일의 바이트 수
+    pub index_bytes: u64,
+    pub elapsed_ms: u64,
+}
+
+impl Stats {
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Started { documents } => self.documents += documents,
+            Event::DocumentIndexed { bytes, words, .. } => {
+                self.documents_indexed += 1;
+                self.bytes_indexed += bytes;
+                self.words_indexed += words;
+            }
+            Event::DocumentSkipped { .. } => self.documents_skipped += 1,
+            Event::TmpFileWritten { bytes, .. } => {
+                self.tmp_files_written += 1;
+                self.tmp_bytes_written += bytes;
+            }
+            Event::Merged { level, .. } => {
+                self.merges += 1;
+                self.merge_levels = self.merge_levels.max(*level);
+            }
+            Event::Finished { bytes, .. } => self.index_bytes = *bytes,
+        }
+    }
+}
+
+/// 표준 에러에 진행 막대를 그리고 통계를 모은다.
+/// 표준 에러가 터미널이 아니면 막대는 그리지 않고 건너뛴 문서만 출력한다.
+pub struct ProgressBar {
+    state: Mutex<BarState>,
+    started: Instant,
+    draw: bool,
+}
+
+struct BarState {
+    stats: Stats,
+    drawn: Option<Instant>,
+}
+
+/// 막대를 다시 그리는 최소 간격
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 막대의 칸 수
+const BAR_WIDTH: usize = 30;
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        ProgressBar {
+            state: Mutex::new(BarState {
+                stats: Stats::default(),
+                drawn: None,
+            }),
+            started: Instant::now(),
+            draw: io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl ProgressBar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 막대를 지우고 지금까지의 통계를 돌려준다.
+    pub fn finish(&self) -> Stats {
+        let state = self.state.lock().unwrap();
+        if self.draw && state.drawn.is_some() {
+            eprint!("\r{:width$}\r", "", width = BAR_WIDTH + 60);
+        }
+        Stats {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            ..state.stats.clone()
+        }
+    }
+
+    fn render(stats: &Stats) -> String {
+        let done = stats.documents_indexed + stats.documents_skipped;
+        let filled = (done * BAR_WIDTH)
+            .checked_div(stats.documents)
+            .unwrap_or(BAR_WIDTH)
+            .min(BAR_WIDTH);
+        format!(
+            "\r[{}{}] {}/{} documents, {} KB, {} tmp files, merge level {}",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            done,
+            stats.documents,
+            stats.bytes_indexed >> 10,
+            stats.tmp_files_written,
+            stats.merge_levels
+        )
+    }
+}
+
+impl Progress for ProgressBar {
+    fn event(&self, event: Event) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.record(&event);
+        if let Event::DocumentSkipped { path, reason } = &event {
+            if self.draw && state.drawn.is_some() {
+                eprint!("\r{:width$}\r", "", width = BAR_WIDTH + 60);
+                state.drawn = None;
+            }
+            eprintln!("warning: skipping {}: {}", path.display(), reason);
+        }
+        if !self.draw || state.drawn.is_some_and(|at| at.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{}", Self::render(&state.stats));
+        let _ = stderr.flush();
+        state.drawn = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let (sender, receiver) = mpsc::channel();
+        let progress: SharedProgress = Arc::new(sender);
+        [
+            Event::Started { documents: 3 },
+            Event::DocumentIndexed {
+                document_id: 0,
+                bytes: 100,
+                words: 20,
+            },
+            Event::DocumentSkipped {
+                path: PathBuf::from("a.bin"),
+                reason: "invalid UTF-8".to_string(),
+            },
+            Event::TmpFileWritten {
+                path: PathBuf::from("tmp00000001.dat"),
+                bytes: 80,
+            },
+            Event::Merged {
+                level: 2,
+                files: 8,
+                bytes: 500,
+            },
+            Event::Finished {
+                path: PathBuf::from("index.dat"),
+                bytes: 600,
+            },
+        ]
+        .into_iter()
+        .for_each(|event| progress.event(event));
+        drop(progress);
+
+        let stats = receiver.iter().fold(Stats::default(), |mut stats, event| {
+            stats.record(&event);
+            stats
+        });
+        assert_eq!(
+            stats,
+            Stats {
+                documents: 3,
+                documents_indexed: 1,
+                documents_skipped: 1,
+                bytes_indexed: 100,
+                words_indexed: 20,
+                tmp_files_written: 1,
+                tmp_bytes_written: 80,
+                merges: 1,
+                merge_levels: 2,
+                index_bytes: 600,
+                elapsed_ms: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,652 @@
+use std::{iter::Peekable, vec};
+
+use crate::{
+    analysis::Analyzer, error::Error, field::Field, index::Posting, search::IndexSearcher,
+};
+
+/// 검색 질의
+/// `rust AND (async OR tokio) NOT unsafe` 같은 질의를 표현한다.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Term(String),
+    /// `gap*`: 접두어로 시작하는 단어
+    Prefix(String),
+    /// `g?p*r`: `*`는 0글자 이상, `?`는 한 글자에 맞는다.
+    Wildcard(String),
+    /// `buffer~2`: 편집 거리가 n 이하인 단어. `buffer~`는 거리 1이다.
+    Fuzzy(String, u32),
+    /// 단어들이 순서대로 붙어서 나오는 곳
+    Phrase(Vec<String>),
+    /// 두 질의의 위치가 n 단어 이내인 곳
+    Near(Box<Query>, Box<Query>, u32),
+    /// 모든 질의에 맞는 문서. 나란히 적은 단어는 한 노드에 모으므로 긴 질의도 깊어지지 않는다.
+    And(Vec<Query>),
+    /// 한 질의에라도 맞는 문서
+    Or(Vec<Query>),
+    /// 왼쪽 결과에서 오른쪽 결과를 뺀다.
+    Not(Box<Query>, Box<Query>),
+    /// `title:rust`: 필드 안에서만 찾는다.
+    Field(Field, Box<Query>),
+}
+
+/// 질의를 이루는 토큰
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Lexeme {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Near(u32),
+    /// `title:` 처럼 필드 이름 뒤에 ':'를 붙인 것
+    Field(Field),
+    Word(String),
+    Phrase(Vec<String>),
+}
+
+fn lex(input: &str) -> Result<Vec<Lexeme>, Error> {
+    let mut lexemes = Vec::new();
+    let mut word = String::new();
+    let mut phrase: Option<String> = None;
+    let flush = |word: &mut String, lexemes: &mut Vec<Lexeme>| {
+        if word.is_empty() {
+            return Ok(());
+        }
+        let lexeme = match word.as_str() {
+            "AND" => Lexeme::And,
+            "OR" => Lexeme::Or,
+            "NOT" => Lexeme::Not,
+            near if near.starts_with("NEAR/") => Lexeme::Near(
+                near["NEAR/".len()..]
+                    .parse()
+                    .map_err(|_| syntax_error(&format!("bad distance in {}", near)))?,
+            ),
+            _ => Lexeme::Word(word.clone()),
+        };
+        lexemes.push(lexeme);
+        word.clear();
+        Ok(())
+    };
+
+    input
+        .chars()
+        .try_for_each(|ch| match (ch, phrase.as_mut()) {
+            ('"', Some(_)) => {
+                let words = phrase
+                    .take()
+                    .unwrap()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                if words.is_empty() {
+                    return Err(syntax_error("empty phrase"));
+                }
+                lexemes.push(Lexeme::Phrase(words));
+                Ok(())
+            }
+            (ch, Some(phrase)) => {
+                phrase.push(ch);
+                Ok(())
+            }
+            ('"', None) => {
+                flush(&mut word, &mut lexemes)?;
+                phrase = Some(String::new());
+                Ok(())
+            }
+            (':', None) if Field::from_name(&word).is_some() => {
+                lexemes.push(Lexeme::Field(Field::from_name(&word).unwrap()));
+                word.clear();
+                Ok(())
+            }
+            ('(' | ')', None) => {
+                flush(&mut word, &mut lexemes)?;
+                lexemes.push(if ch == '(' {
+                    Lexeme::Open
+                } else {
+                    Lexeme::Close
+                });
+                Ok(())
+            }
+            (ch, None) if ch.is_whitespace() => flush(&mut word, &mut lexemes),
+            (ch, None) => {
+                word.push(ch);
+                Ok(())
+            }
+        })?;
+    if phrase.is_some() {
+        return Err(syntax_error("missing '\"'"));
+    }
+    flush(&mut word, &mut lexemes)?;
+
+    Ok(lexemes)
+}
+
+/// `~` 뒤에 거리를 적지 않았을 때의 편집 거리
+pub const DEFAULT_FUZZY_DISTANCE: u32 = 1;
+
+/// 단어 하나를 질의로 바꾼다. `*`, `?`, `~`가 있으면 단어 사전에서 찾을 패턴이다.
+fn word_query(word: String) -> Result<Query, Error> {
+    if let Some((term, distance)) = word.rsplit_once('~') {
+        if term.is_empty() {
+            return Err(syntax_error(&format!("missing word in {}", word)));
+        }
+        let distance = match distance {
+            "" => DEFAULT_FUZZY_DISTANCE,
+            n => n
+                .parse()
+                .map_err(|_| syntax_error(&format!("bad distance in {}", word)))?,
+        };
+        return Ok(Query::Fuzzy(term.to_string(), distance));
+    }
+    if !word.contains(['*', '?']) {
+        return Ok(Query::Term(word));
+    }
+    if word.chars().all(|ch| ch == '*' || ch == '?') {
+        return Err(syntax_error(&format!("{} matches every word", word)));
+    }
+    match word.strip_suffix('*') {
+        Some(prefix) if !prefix.contains(['*', '?']) => Ok(Query::Prefix(prefix.to_string())),
+        _ => Ok(Query::Wildcard(word)),
+    }
+}
+
+fn syntax_error(message: &str) -> Error {
+    Error::InvalidQuery(message.to_string())
+}
+
+/// 질의를 중첩할 수 있는 깊이. 괄호, 필드, NOT, NEAR가 한 단계씩 깊어진다.
+/// 해석과 평가가 재귀하므로 깊이를 제한해야 스택이 넘치지 않는다.
+pub const MAX_QUERY_DEPTH: usize = 256;
+
+/// 질의 하나로 묶는다. 하나뿐이면 그대로 돌려준다.
+fn collapse(mut queries: Vec<Query>, group: fn(Vec<Query>) -> Query) -> Query {
+    match queries.len() {
+        1 => queries.pop().unwrap(),
+        _ => group(queries),
+    }
+}
+
+/// 재귀 하강 파서
+/// or  := and ("OR" and)*
+/// and := near (["AND"] ["NOT"] near)*
+/// near := primary ("NEAR/n" primary)*
+/// primary := FIELD ":" primary | "(" or ")" | WORD | PATTERN | "\"" WORD* "\""
+struct Parser {
+    lexemes: Peekable<vec::IntoIter<Lexeme>>,
+    /// 지금 해석하고 있는 곳의 깊이
+    depth: usize,
+}
+
+impl Parser {
+    /// 한 단계 깊이 들어간다. `MAX_QUERY_DEPTH`를 넘으면 오류를 돌려준다.
+    fn descend(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > MAX_QUERY_DEPTH {
+            return Err(syntax_error(&format!(
+                "query is nested more than {} levels deep",
+                MAX_QUERY_DEPTH
+            )));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Query, Error> {
+        let mut queries = vec![self.parse_and()?];
+        while self.lexemes.next_if_eq(&Lexeme::Or).is_some() {
+            queries.push(self.parse_and()?);
+        }
+        Ok(collapse(queries, Query::Or))
+    }
+
+    /// NOT은 그때까지 모은 질의를 왼쪽으로 하는 노드를 만든다.
+    fn parse_and(&mut self) -> Result<Query, Error> {
+        if self.lexemes.peek() == Some(&Lexeme::Not) {
+            return Err(syntax_error("NOT needs a left operand"));
+        }
+        let depth = self.depth;
+        let mut queries = vec![self.parse_near()?];
+        while !matches!(
+            self.lexemes.peek(),
+            None | Some(Lexeme::Close) | Some(Lexeme::Or)
+        ) {
+            self.lexemes.next_if_eq(&Lexeme::And);
+            if self.lexemes.next_if_eq(&Lexeme::Not).is_some() {
+                self.descend()?;
+                let right = self.parse_near()?;
+                let left = collapse(queries, Query::And);
+                queries = vec![Query::Not(Box::new(left), Box::new(right))];
+            } else {
+                queries.push(self.parse_near()?);
+            }
+        }
+        self.depth = depth;
+        Ok(collapse(queries, Query::And))
+    }
+
+    fn parse_near(&mut self) -> Result<Query, Error> {
+        let depth = self.depth;
+        let mut query = self.parse_primary()?;
+        while let Some(Lexeme::Near(distance)) = self
+            .lexemes
+            .next_if(|lexeme| matches!(lexeme, Lexeme::Near(_)))
+        {
+            self.descend()?;
+            let right = self.parse_primary()?;
+            query = Query::Near(Box::new(query), Box::new(right), distance);
+        }
+        self.depth = depth;
+        Ok(query)
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, Error> {
+        match self.lexemes.next() {
+            Some(Lexeme::Word(word)) => word_query(word),
+            Some(Lexeme::Phrase(mut words)) if words.len() == 1 => {
+                Ok(Query::Term(words.pop().unwrap()))
+            }
+            Some(Lexeme::Phrase(words)) => Ok(Query::Phrase(words)),
+            Some(Lexeme::Field(field)) => {
+                self.descend()?;
+                let query = self.parse_primary()?;
+                self.depth -= 1;
+                Ok(Query::Field(field, Box::new(query)))
+            }
+            Some(Lexeme::Open) => {
+                self.descend()?;
+                let query = self.parse_or()?;
+                self.depth -= 1;
+                match self.lexemes.next() {
+                    Some(Lexeme::Close) => Ok(query),
+                    _ => Err(syntax_error("missing ')'")),
+                }
+            }
+            Some(lexeme) => Err(syntax_error(&format!("unexpected {:?}", lexeme))),
+            None => Err(syntax_error("unexpected end of query")),
+        }
+    }
+}
+
+impl Query {
+    /// 질의 문자열을 해석한다.
+    /// 연산자 없이 나란히 적은 단어는 AND로 묶는다.
+    /// `MAX_QUERY_DEPTH`보다 깊이 중첩한 질의는 `Error::InvalidQuery`를 돌려준다.
+    pub fn parse(input: &str) -> Result<Query, Error> {
+        let mut parser = Parser {
+            lexemes: lex(input)?.into_iter().peekable(),
+            depth: 0,
+        };
+        let query = parser.parse_or()?;
+        match parser.lexemes.next() {
+            None => Ok(query),
+            Some(lexeme) => Err(syntax_error(&format!("unexpected {:?}", lexeme))),
+        }
+    }
+
+    /// 점수 계산에 쓸 인덱스의 단어 목록. `IndexSearcher::postings`로 그대로 찾는다.
+    /// NOT의 오른쪽에 있는 단어는 빼고 모은다. 단어와 구절은 분석기로 나누고, 패턴은 인덱스에서 맞는 단어로 바꾼다.
+    pub fn terms(&self, searcher: &IndexSearcher) -> Result<Vec<String>, Error> {
+        let analyze = |text: &str| {
+            searcher
+                .analyzer()
+                .analyze(text)
+                .into_iter()
+                .map(|token| token.text)
+        };
+        match self {
+            Query::Term(term) => Ok(analyze(term).collect()),
+            Query::Prefix(_) | Query::Wildcard(_) | Query::Fuzzy(..) => self.expand(searcher),
+            Query::Phrase(terms) => Ok(terms.iter().flat_map(|term| analyze(term)).collect()),
+            Query::And(queries) | Query::Or(queries) => {
+                queries.iter().try_fold(Vec::new(), |mut terms, query| {
+                    terms.extend(query.terms(searcher)?);
+                    Ok(terms)
+                })
+            }
+            Query::Near(left, right, _) => {
+                let mut terms = left.terms(searcher)?;
+                terms.extend(right.terms(searcher)?);
+                Ok(terms)
+            }
+            Query::Not(left, _) => left.terms(searcher),
+            Query::Field(_, query) => query.terms(searcher),
+        }
+    }
+
+    /// 패턴에 맞는 인덱스의 단어를 단어 순으로 돌려준다. 패턴이 아니면 빈 목록이다.
+    /// 패턴은 나누지 않고 분석기의 대소문자, 유니코드 정규화만 적용해서 찾는다.
+    pub fn expand(&self, searcher: &IndexSearcher) -> Result<Vec<String>, Error> {
+        let normalize = |text: &str| searcher.analyzer().normalize(text);
+        let found = match self {
+            Query::Prefix(prefix) => searcher.terms().prefix(&normalize(prefix)),
+            Query::Wildcard(pattern) => searcher.terms().wildcard(&normalize(pattern)),
+            Query::Fuzzy(term, distance) => searcher.terms().fuzzy(&normalize(term), *distance)?,
+            _ => Vec::new(),
+        };
+        Ok(found
+            .into_iter()
+            .map(|i| searcher.entry_at(i).term.clone())
+            .collect())
+    }
+
+    /// 질의에 맞는 문서를 문서 번호 순으로 돌려준다.
+    pub fn evaluate(&self, searcher: &IndexSearcher) -> Result<Vec<Posting>, Error> {
+        self.evaluate_in(searcher, None)
+    }
+
+    /// field가 있으면 그 필드에서만 찾는다. 안쪽에 다른 필드를 적으면 안쪽 필드를 쓴다.
+    fn evaluate_in(
+        &self,
+        searcher: &IndexSearcher,
+        field: Option<Field>,
+    ) -> Result<Vec<Posting>, Error> {
+        match self {
+            Query::Term(term) => searcher.search_in(term, field),
+            Query::Prefix(_) | Query::Wildcard(_) | Query::Fuzzy(..) => self
+                .expand(searcher)?
+                .iter()
+                .try_fold(Vec::new(), |postings, term| {
+                    Ok(union(postings, searcher.postings_in(term, field)?))
+                }),
+            Query::Phrase(terms) => searcher.search_in(&terms.join(" "), field),
+            Query::Near(left, right, distance) => Ok(near(
+                left.evaluate_in(searcher, field)?,
+                right.evaluate_in(searcher, field)?,
+                *distance,
+            )),
+            Query::And(queries) => {
+                let (first, rest) = queries.split_first().expect("AND without operands");
+                rest.iter()
+                    .try_fold(first.evaluate_in(searcher, field)?, |postings, query| {
+                        Ok(intersect(postings, query.evaluate_in(searcher, field)?))
+                    })
+            }
+            Query::Or(queries) => queries.iter().try_fold(Vec::new(), |postings, query| {
+                Ok(union(postings, query.evaluate_in(searcher, field)?))
+            }),
+            Query::Not(left, right) => Ok(difference(
+                left.evaluate_in(searcher, field)?,
+                right.evaluate_in(searcher, field)?,
+            )),
+            Query::Field(field, query) => query.evaluate_in(searcher, Some(*field)),
+        }
+    }
+}
+
+/// 두 위치 목록을 합친다.
+fn merge_positions(mut a: Vec<u32>, b: Vec<u32>) -> Vec<u32> {
+    a.extend(b);
+    a.sort_unstable();
+    a.dedup();
+    a
+}
+
+/// 두 목록에 모두 있는 문서
+/// 목록은 문서 번호 순으로 정렬되어 있어야 한다.
+pub fn intersect(a: Vec<Posting>, b: Vec<Posting>) -> Vec<Posting> {
+    intersect_by(a, b, merge_positions)
+}
+
+/// 두 목록 중 한 곳에라도 있는 문서
+/// 목록은 문서 번호 순으로 정렬되어 있어야 한다.
+pub fn union(a: Vec<Posting>, b: Vec<Posting>) -> Vec<Posting> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()));
+    let mut b = b.into_iter().peekable();
+    a.into_iter().for_each(|x| {
+        while let Some(y) = b.next_if(|y| y.document_id < x.document_id) {
+            result.push(y);
+        }
+        match b.next_if(|y| y.document_id == x.document_id) {
+            Some(y) => result.push(Posting {
+                document_id: x.document_id,
+                positions: merge_positions(x.positions, y.positions),
+            }),
+            None => result.push(x),
+        }
+    });
+    result.extend(b);
+    result
+}
+
+/// a에는 있지만 b에는 없는 문서
+/// 목록은 문서 번호 순으로 정렬되어 있어야 한다.
+pub fn difference(a: Vec<Posting>, b: Vec<Posting>) -> Vec<Posting> {
+    let mut b = b.into_iter().peekable();
+    a.into_iter()
+        .filter(|x| {
+            while b.next_if(|y| y.document_id < x.document_id).is_some() {}
+            b.next_if(|y| y.document_id == x.document_id).is_none()
+        })
+        .collect()
+}
+
+/// 단어들이 순서대로 붙어 있는 문서
+/// 결과의 위치는 구절이 시작하는 위치이다.
+pub fn phrase(lists: Vec<Vec<Posting>>) -> Vec<Posting> {
+    phrase_at(
+        lists
+            .into_iter()
+            .enumerate()
+            .map(|(i, list)| (i as u32, list))
+            .collect(),
+    )
+}
+
+/// 단어들이 (위치, 목록)에 적힌 위치 차이만큼 떨어져 있는 문서
+/// 불용어가 빠진 자리나 같은 위치의 n-gram처럼 위치가 띄엄띄엄하거나 겹칠 수 있다.
+/// 위치는 첫 단어의 위치보다 작지 않아야 하고, 결과의 위치는 첫 단어의 위치이다.
+pub fn phrase_at(lists: Vec<(u32, Vec<Posting>)>) -> Vec<Posting> {
+    let mut lists = lists.into_iter();
+    let (base, first) = lists.next().unwrap_or_default();
+    lists.fold(first, |starts, (position, next)| {
+        let offset = position - base;
+        intersect_with(starts, next, |starts, next| {
+            starts
+                .into_iter()
+                .filter(|start| next.binary_search(&(start + offset)).is_ok())
+                .collect()
+        })
+    })
+}
+
+/// 두 질의의 위치가 distance 단어 이내로 가까운 문서
+/// 결과의 위치는 조건을 만족하는 양쪽의 위치이다.
+pub fn near(a: Vec<Posting>, b: Vec<Posting>, distance: u32) -> Vec<Posting> {
+    intersect_with(a, b, |x, y| {
+        let close = |p: u32, others: &[u32]| {
+            // 거리가 아주 크면 위치를 넘어서므로 더하지 않고 u32::MAX에서 멈춘다.
+            let i = others.partition_point(|&q| q.saturating_add(distance) < p);
+            others
+                .get(i)
+                .is_some_and(|&q| q <= p.saturating_add(distance))
+        };
+        let matched = x
+            .iter()
+            .filter(|&&p| close(p, &y))
+            .chain(y.iter().filter(|&&q| close(q, &x)))
+            .copied()
+            .collect();
+        merge_positions(matched, Vec::new())
+    })
+}
+
+/// 두 목록에 모두 있는 문서의 위치를 f로 합친다.
+/// 합친 위치가 비어 있는 문서는 버린다.
+fn intersect_with<F>(a: Vec<Posting>, b: Vec<Posting>, f: F) -> Vec<Posting>
+where
+    F: Fn(Vec<u32>, Vec<u32>) -> Vec<u32>,
+{
+    intersect_by(a, b, f)
+        .into_iter()
+        .filter(|posting| !posting.positions.is_empty())
+        .collect()
+}
+
+fn intersect_by<F>(a: Vec<Posting>, b: Vec<Posting>, f: F) -> Vec<Posting>
+where
+    F: Fn(Vec<u32>, Vec<u32>) -> Vec<u32>,
+{
+    let mut result = Vec::new();
+    let mut b = b.into_iter().peekable();
+    a.into_iter().for_each(|x| {
+        while b.next_if(|y| y.document_id < x.document_id).is_some() {}
+        if let Some(y) = b.next_if(|y| y.document_id == x.document_id) {
+            result.push(Posting {
+                document_id: x.document_id,
+                positions: f(x.positions, y.positions),
+            });
+        }
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(word: &str) -> Box<Query> {
+        Box::new(Query::Term(word.to_string()))
+    }
+
+    fn postings(ids: &[u32]) -> Vec<Posting> {
+        ids.iter()
+            .map(|&document_id| Posting {
+                document_id,
+                positions: vec![document_id],
+            })
+            .collect()
+    }
+
+    fn ids(postings: &[Posting]) -> Vec<u32> {
+        postings.iter().map(|p| p.document_id).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Query::parse("rust AND (async OR tokio) NOT unsafe").unwrap(),
+            Query::Not(
+                Box::new(Query::And(vec![
+                    Query::Term("rust".to_string()),
+                    Query::Or(vec![
+                        Query::Term("async".to_string()),
+                        Query::Term("tokio".to_string())
+                    ])
+                ])),
+                term("unsafe")
+            )
+        );
+        assert_eq!(
+            Query::parse("gap buffer OR rope").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![
+                    Query::Term("gap".to_string()),
+                    Query::Term("buffer".to_string())
+                ]),
+                Query::Term("rope".to_string())
+            ])
+        );
+        assert!(Query::parse("NOT unsafe").is_err());
+        assert!(Query::parse("(rust").is_err());
+        assert!(Query::parse("rust)").is_err());
+        assert!(Query::parse("rust OR").is_err());
+        assert_eq!(
+            Query::parse("\"gap buffer\" NEAR/5 rust OR \"rope\"").unwrap(),
+            Query::Or(vec![
+                Query::Near(
+                    Box::new(Query::Phrase(vec!["gap".to_string(), "buffer".to_string()])),
+                    term("rust"),
+                    5
+                ),
+                Query::Term("rope".to_string())
+            ])
+        );
+        assert!(Query::parse("\"gap buffer").is_err());
+        assert!(Query::parse("gap NEAR/x buffer").is_err());
+        assert_eq!(
+            Query::parse("gap* g?p*r buffer~ buffer~2").unwrap(),
+            Query::And(vec![
+                Query::Prefix("gap".to_string()),
+                Query::Wildcard("g?p*r".to_string()),
+                Query::Fuzzy("buffer".to_string(), 1),
+                Query::Fuzzy("buffer".to_string(), 2)
+            ])
+        );
+        assert!(Query::parse("*").is_err());
+        assert!(Query::parse("buffer~x").is_err());
+
+        assert_eq!(
+            Query::parse("title:(rust OR \"gap buffer\") tags:gap* std::io").unwrap(),
+            Query::And(vec![
+                Query::Field(
+                    Field::Title,
+                    Box::new(Query::Or(vec![
+                        Query::Term("rust".to_string()),
+                        Query::Phrase(vec!["gap".to_string(), "buffer".to_string()])
+                    ]))
+                ),
+                Query::Field(Field::Tags, Box::new(Query::Prefix("gap".to_string()))),
+                Query::Term("std::io".to_string())
+            ])
+        );
+        assert!(Query::parse("title:").is_err());
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let nested = |depth: usize| format!("{}rust{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(
+            Query::parse(&nested(MAX_QUERY_DEPTH)).unwrap(),
+            Query::Term("rust".to_string())
+        );
+        assert!(matches!(
+            Query::parse(&nested(60_000)),
+            Err(Error::InvalidQuery(_))
+        ));
+        let fields = format!("{}rust", "title:".repeat(MAX_QUERY_DEPTH + 1));
+        assert!(Query::parse(&fields).is_err());
+        let nots = format!("rust{}", " NOT gap".repeat(MAX_QUERY_DEPTH + 1));
+        assert!(Query::parse(&nots).is_err());
+
+        // 나란히 적은 단어는 깊어지지 않는다.
+        let words = vec!["gap"; 30_000].join(" ");
+        match Query::parse(&words).unwrap() {
+            Query::And(queries) => assert_eq!(queries.len(), 30_000),
+            query => panic!("expected AND, got {:?}", query),
+        }
+        let alternatives = vec!["gap"; 30_000].join(" OR ");
+        assert!(matches!(Query::parse(&alternatives), Ok(Query::Or(_))));
+    }
+
+    #[test]
+    fn test_phrase_and_near() {
+        let at = |document_id, positions: &[u32]| Posting {
+            document_id,
+            positions: positions.to_vec(),
+        };
+        let gap = vec![at(1, &[1, 8]), at(2, &[4])];
+        let buffer = vec![at(1, &[2, 20]), at(2, &[7])];
+        assert_eq!(phrase(vec![gap.clone(), buffer.clone()]), [at(1, &[1])]);
+        assert_eq!(
+            phrase_at(vec![(5, gap.clone()), (8, buffer.clone())]),
+            [at(2, &[4])]
+        );
+        assert_eq!(
+            near(gap.clone(), buffer.clone(), 3),
+            [at(1, &[1, 2]), at(2, &[4, 7])]
+        );
+        assert_eq!(near(gap.clone(), buffer.clone(), 2), [at(1, &[1, 2])]);
+        assert_eq!(
+            near(gap, buffer, u32::MAX),
+            [at(1, &[1, 2, 8, 20]), at(2, &[4, 7])]
+        );
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = postings(&[1, 3, 5, 7]);
+        let b = postings(&[2, 3, 7, 8]);
+        assert_eq!(ids(&intersect(a.clone(), b.clone())), [3, 7]);
+        assert_eq!(ids(&union(a.clone(), b.clone())), [1, 2, 3, 5, 7, 8]);
+        assert_eq!(ids(&difference(a, b)), [1, 5]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{documents::DocumentTable, error::Error, index::Posting, search::IndexSearcher};
+
+/// BM25 점수 계산기
+#[derive(Debug, Clone, Copy)]
+pub struct Bm25 {
+    /// 단어 빈도의 포화 정도
+    pub k1: f64,
+    /// 문서 길이로 정규화하는 정도
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+/// 점수가 매겨진 문서
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredDocument {
+    pub document_id: u32,
+    pub score: f64,
+}
+
+impl Bm25 {
+    pub fn new(k1: f64, b: f64) -> Self {
+        Bm25 { k1, b }
+    }
+
+    /// 단어가 하나라도 나오는 모든 문서의 점수를 구한다.
+    /// terms는 인덱스의 단어이고 `query::Query::terms`로 구한다. 다시 분석하지 않고 그대로 찾는다.
+    /// 단어 빈도는 위치 목록의 길이, 문서 길이는 문서 테이블의 단어 수를 쓴다.
+    /// 문서 수와 평균 길이는 지운 것으로 표시한 문서를 빼고 센다.
+    pub fn scores(
+        &self,
+        searcher: &IndexSearcher,
+        documents: &DocumentTable,
+        terms: &[String],
+    ) -> Result<HashMap<u32, f64>, Error> {
+        let (live, total_words) = documents
+            .iter()
+            .filter(|d| !searcher.tombstones().contains(d.id))
+            .fold((0, 0), |(live, words), d| (live + 1, words + d.word_count));
+        let n = live.max(1) as f64;
+        let avgdl = (total_words as f64 / n).max(1.0);
+
+        terms.iter().try_fold(HashMap::new(), |mut scores, term| {
+            let postings = searcher.postings(term)?;
+            let df = postings.len() as f64;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            postings.iter().for_each(|posting| {
+                let tf = posting.positions.len() as f64;
+                let dl = documents
+                    .get(posting.document_id)
+                    .map_or(avgdl, |d| d.word_count as f64);
+                let norm = self.k1 * (1.0 - self.b + self.b * dl / avgdl);
+                *scores.entry(posting.document_id).or_insert(0.0) +=
+                    idf * tf * (self.k1 + 1.0) / (tf + norm);
+            });
+            Ok(scores)
+        })
+    }
+
+    /// 단어 목록으로 점수가 높은 문서 k개를 찾는다.
+    pub fn top_k(
+        &self,
+        searcher: &IndexSearcher,
+        documents: &DocumentTable,
+        terms: &[String],
+        k: usize,
+    ) -> Result<Vec<ScoredDocument>, Error> {
+        let scores = self.scores(searcher, documents, terms)?;
+        Ok(best(
+            scores
+                .into_iter()
+                .map(|(document_id, score)| ScoredDocument { document_id, score }),
+            k,
+        ))
+    }
+
+    /// 질의에 맞는 문서들을 단어 목록의 점수로 정렬해서 k개를 고른다.
+    pub fn rank(
+        &self,
+        searcher: &IndexSearcher,
+        documents: &DocumentTable,
+        terms: &[String],
+        matches: &[Posting],
+        k: usize,
+    ) -> Result<Vec<ScoredDocument>, Error> {
+        let scores = self.scores(searcher, documents, terms)?;
+        Ok(best(
+            matches.iter().map(|posting| ScoredDocument {
+                document_id: posting.document_id,
+                score: scores.get(&posting.document_id).copied().unwrap_or(0.0),
+            }),
+            k,
+        ))
+    }
+}
+
+/// 점수가 높은 순으로 k개를 고른다. 점수가 같으면 문서 번호 순이다.
+fn best<I: Iterator<Item = ScoredDocument>>(scored: I, k: usize) -> Vec<ScoredDocument> {
+    let mut scored = scored.collect::<Vec<_>>();
+    scored.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.document_id.cmp(&b.document_id))
+    });
+    scored.truncate(k);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::Pipeline,
+        documents::documents_path,
+        query::Query,
+        test_util::{build_index, TestDir},
+        tombstone::{tombstones_path, Tombstones},
+    };
+
+    #[test]
+    fn test_scores() {
+        let dir = TestDir::new("rank");
+        let analyzer = Pipeline::from_spec("lowercase,stem").unwrap();
+        let index_path = build_index(
+            &dir,
+            &analyzer,
+            &[
+                ("a.txt", "universities rank"),
+                ("b.txt", "ranking tables"),
+                ("c.txt", "old universities"),
+            ],
+        );
+        let mut tombstones = Tombstones::new();
+        tombstones.insert(1);
+        tombstones.write(tombstones_path(&index_path)).unwrap();
+        let searcher = IndexSearcher::open(&index_path).unwrap();
+        let documents = DocumentTable::open(documents_path(&index_path)).unwrap();
+
+        // 패턴이 찾은 단어는 이미 분석한 단어이므로 다시 어간을 자르면 안 된다.
+        let terms = Query::parse("univers*").unwrap().terms(&searcher).unwrap();
+        assert_eq!(terms, ["univers"]);
+        let scores = Bm25::default()
+            .scores(&searcher, &documents, &terms)
+            .unwrap();
+        assert_eq!(scores.len(), 2);
+        assert!(scores[&0] > 0.0);
+
+        // 지운 것으로 표시한 문서는 문서 수에 넣지 않는다.
+        let mut live = DocumentTable::new();
+        documents
+            .iter()
+            .filter(|document| document.id != 1)
+            .for_each(|document| live.push(document.clone()));
+        let terms = Query::parse("rank").unwrap().terms(&searcher).unwrap();
+        assert_eq!(
+            Bm25::default()
+                .scores(&searcher, &documents, &terms)
+                .unwrap(),
+            Bm25::default().scores(&searcher, &live, &terms).unwrap()
+        );
+    }
+}
@@ -6,7 +6,10 @@ use std::{
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use crate::write::IndexFileWriter;
+use crate::{
+    error::{Error, PathContext},
+    header::{FormatError, Header},
+};
 
 /// 파일을 처음부터 마지막까지 훑는다.
 pub struct IndexFileReader {
@@ -17,6 +20,10 @@ pub struct IndexFileReader {
     /// 테이블의 다음 엔트리, None이면 테이블의 마지막이다.
     next: Option<Entry>,
     filename: PathBuf,
+    /// 색인할 때 쓴 분석기의 설정
+    analyzer: String,
+    /// drop할 때 파일을 지우지 않는다.
+    keep: bool,
 }
 
 /// 인덱스 파일의 테이블이다
@@ -33,26 +40,37 @@ pub struct Entry {
 
 impl Drop for IndexFileReader {
     fn drop(&mut self) {
-        let _ = self.delete();
+        if !self.keep {
+            let _ = self.delete();
+        }
     }
 }
 
 /// 파일 구조
-/// [offset: 8B][hits(n):8 * n Byte](offset->|)[[hits_offset: 8B][hits_byte_len: 8B][hits_len: 4B][term_len: 4B][term: term_len B]...]
+/// 헤더의 구조는 `header::Header`, Posting 목록의 구조는 `index::PostingListBuilder` 참고
+/// [header: Header::size() B][postings(n)](offset->|)[[hits_offset: 8B][hits_byte_len: 8B][hits_len: 4B][term_len: 4B][term: term_len B]...]
 impl IndexFileReader {
-    /// 인덱스 파일을 열어서 처음부터 마지막까지 읽는다.
-    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexFileReader, io::Error> {
+    /// 인덱스 파일을 열어서 처음부터 마지막까지 읽는다. drop하면 파일을 지운다.
+    /// 헤더가 맞지 않거나 체크섬이 다르면 `Error::CorruptIndex`를 돌려준다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexFileReader, Error> {
         let filename = filename.as_ref();
+        IndexFileReader::open_at(filename, false).with_path(filename)
+    }
+
+    /// `open`과 같지만 drop해도 파일을 지우지 않는다. 기존 인덱스를 복사하지 않고 병합할 때 쓴다.
+    pub fn open_kept<P: AsRef<Path>>(filename: P) -> Result<IndexFileReader, Error> {
+        let filename = filename.as_ref();
+        IndexFileReader::open_at(filename, true).with_path(filename)
+    }
+
+    fn open_at(filename: &Path, keep: bool) -> Result<IndexFileReader, Error> {
         let mut main_raw = File::open(filename)?;
 
-        let content_offset = main_raw.read_u64::<LittleEndian>()?;
-        println!(
-            "opened {}, table of contents starts at {}",
-            filename.display(),
-            content_offset
-        );
+        let header = Header::read_verified(&mut main_raw)?;
+        let content_offset = header.contents_offset;
         let mut contents_raw = File::open(filename)?;
         contents_raw.seek(SeekFrom::Start(content_offset))?;
+        main_raw.seek(SeekFrom::Start(header.size()))?;
 
         let main = BufReader::new(main_raw);
         let mut contents = BufReader::new(contents_raw);
@@ -64,16 +82,29 @@ impl IndexFileReader {
             contents,
             next: first,
             filename: filename.to_path_buf(),
+            analyzer: header.analyzer,
+            keep,
         })
     }
 
+    /// 읽고 있는 파일
+    pub fn filename(&self) -> &Path {
+        &self.filename
+    }
+
+    /// 색인할 때 쓴 분석기의 설정
+    pub fn analyzer(&self) -> &str {
+        &self.analyzer
+    }
+
     /// 파일 디스크립터 해제하고 파일을 삭제한다.
-    pub fn delete(&mut self) -> Result<(), io::Error> {
-        fs::remove_file(&self.filename)
+    pub fn delete(&mut self) -> Result<(), Error> {
+        fs::remove_file(&self.filename).with_path(&self.filename)
     }
 
-    /// 다음 Entry를 읽는다.
-    pub fn read_entry(f: &mut BufReader<File>) -> Result<Option<Entry>, io::Error> {
+    /// 다음 Entry를 읽는다. 테이블이 끝나면 None을 돌려준다.
+    /// 단어가 UTF-8이 아니면 `Error::BadUtf8Term`을 돌려준다.
+    pub fn read_entry<R: Read>(f: &mut R) -> Result<Option<Entry>, Error> {
         let offset = match f.read_u64::<LittleEndian>() {
             Ok(value) => value,
             Err(e) => {
@@ -81,7 +112,7 @@ impl IndexFileReader {
                     // 더 이상 읽을 것이 없다.
                     return Ok(None);
                 } else {
-                    return Err(e);
+                    return Err(e.into());
                 }
             }
         };
@@ -89,10 +120,18 @@ impl IndexFileReader {
         let nbytes = f.read_u64::<LittleEndian>()?;
         let df = f.read_u32::<LittleEndian>()?;
         let term_len = f.read_u32::<LittleEndian>()? as usize;
-        let mut bytes = vec![0; term_len];
-        f.read_exact(&mut bytes)?;
-        let term = String::from_utf8(bytes)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "unicode fail"))?;
+        // 손상된 테이블의 term_len만큼 미리 할당하지 않도록 읽은 만큼만 늘린다.
+        let mut bytes = Vec::new();
+        Read::by_ref(f)
+            .take(term_len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != term_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let term = String::from_utf8(bytes).map_err(|e| Error::BadUtf8Term {
+            path: None,
+            bytes: e.into_bytes(),
+        })?;
 
         Ok(Some(Entry {
             term,
@@ -115,22 +154,22 @@ impl IndexFileReader {
         }
     }
 
-    pub fn move_entry_to(&mut self, out: &mut IndexFileWriter) -> Result<(), io::Error> {
-        {
+    /// 다음 엔트리의 인덱스 데이터를 읽고 그 다음 엔트리로 넘어간다.
+    pub fn take_hits(&mut self) -> Result<Vec<u8>, Error> {
+        let buf = {
             let e = self.next.as_ref().expect("no entry to move");
             if e.nbytes > usize::MAX as u64 {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "computer not big enough to hold index entry",
-                ));
+                return Err(
+                    Error::from(FormatError::EntryTooLarge(e.nbytes)).with_path(&self.filename)
+                );
             }
             let mut buf = vec![0; e.nbytes as usize];
-            self.main.read_exact(&mut buf)?;
-            out.write_main(&buf)?;
-        }
+            self.main.read_exact(&mut buf).with_path(&self.filename)?;
+            buf
+        };
 
-        self.next = Self::read_entry(&mut self.contents)?;
+        self.next = Self::read_entry(&mut self.contents).with_path(&self.filename)?;
 
-        Ok(())
+        Ok(buf)
     }
 }
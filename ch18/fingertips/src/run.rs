@@ -1,187 +1,407 @@
 use std::{
-    fs::File,
+    collections::HashSet,
+    fs::{self, File},
     io::{self, Read},
-    ops::ControlFlow,
+    iter,
+    ops::{ControlFlow, Range},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Condvar, Mutex,
+    },
     thread::{self, spawn, JoinHandle},
 };
 
 use crate::{
-    index::InMemoryIndex, merge::FileMerge, parse_args::Args, tmp::TmpDir,
+    analysis::{Pipeline, DEFAULT_ANALYZER},
+    documents::{documents_path, mtime_of, Document, DocumentTable},
+    error::{Error, PathContext},
+    extract::{extract_records, InvalidUtf8, Record},
+    header::{Header, FORMAT_VERSION},
+    highlight::{term_positions, Highlighter},
+    index::InMemoryIndex,
+    inspect::IndexFileInfo,
+    merge::FileMerge,
+    parse_args::{Args, DeleteArgs, InspectArgs, SearchArgs},
+    progress::{Event, Progress, SharedProgress},
+    query::Query,
+    rank::Bm25,
+    search::IndexSearcher,
+    tmp::TmpDir,
+    tombstone::{excluded_path, tombstones_path, ExcludedPaths, Tombstones},
+    walk::expand_filename_arguments,
     write::write_index_to_tmp_file,
 };
 
-/// 순회할 파일 목록을 작성한다.
-/// `io::Result<()>` 보다 `Result<(), io::Error>` 이 가독성이 나은 것 같아서 대신 사용한다.
-/// for 보다 try_fold나 try_for_each이 보기 좋은 것 같아서 대신 사용한다.
-/// 흐름을 따라서 쭉 읽으면 코드가 바로 파악된다.
-fn expand_filename_arguments(args_filenames: &[String]) -> Result<Vec<PathBuf>, io::Error> {
-    args_filenames
-        .iter()
-        .map(PathBuf::from)
-        .try_fold(vec![], |mut filenames, path| {
-            if path.metadata()?.is_dir() {
-                path.read_dir()?.try_for_each(|entry| {
-                    let entry = entry?;
-                    if entry.file_type()?.is_file() {
-                        filenames.push(entry.path());
-                    }
-                    <Result<(), io::Error>>::Ok(())
-                })?;
-            } else {
-                filenames.push(path);
+/// 파일 시스템에서 읽은 문서
+struct SourceFile {
+    path: PathBuf,
+    /// 파일의 바이트 수
+    len: u64,
+    mtime: u64,
+    /// 파일에서 뽑은 문서들. JSON-lines 파일이 아니면 하나이다.
+    records: Vec<Record>,
+    /// 문서들에 매길 번호. 파일을 읽은 뒤에 `DocumentIds`로 정한다.
+    ids: Range<usize>,
+}
+
+/// 문서를 메모리로 읽고 색인할 텍스트를 뽑는다.
+fn read_source_file(path: PathBuf, invalid_utf8: InvalidUtf8) -> Result<SourceFile, Error> {
+    let mut f = File::open(&path)?;
+    let metadata = f.metadata()?;
+    let mtime = mtime_of(metadata.modified()?);
+    let mut bytes = Vec::new();
+    f.read_to_end(&mut bytes)?;
+    let records = extract_records(&path, bytes, invalid_utf8)?;
+    Ok(SourceFile {
+        path,
+        len: metadata.len(),
+        mtime,
+        records,
+        ids: 0..0,
+    })
+}
+
+/// 파일 순서대로 문서 번호를 나눠준다.
+/// 파일마다 레코드 수가 다르므로 앞의 파일들을 읽어야 번호를 정할 수 있다.
+/// 파일은 여러 스레드가 함께 읽고 번호만 파일 순서대로 받으므로, 스레드 수와 관계없이 번호가 같다.
+struct DocumentIds {
+    /// (번호를 받을 차례인 파일, 그 파일의 첫 번호)
+    next: Mutex<(usize, usize)>,
+    turn: Condvar,
+}
+
+impl DocumentIds {
+    fn new(first_id: usize) -> DocumentIds {
+        DocumentIds {
+            next: Mutex::new((0, first_id)),
+            turn: Condvar::new(),
+        }
+    }
+
+    /// file번째 파일의 문서 count개에 번호를 매긴다.
+    /// 앞의 파일들이 모두 번호를 받을 때까지 기다린다.
+    fn assign(&self, file: usize, count: usize) -> Range<usize> {
+        let mut next = self
+            .turn
+            .wait_while(self.next.lock().unwrap(), |(turn, _)| *turn != file)
+            .unwrap();
+        let ids = next.1..next.1 + count;
+        *next = (file + 1, ids.end);
+        self.turn.notify_all();
+        ids
+    }
+}
+
+/// file번째 문서를 읽고 번호를 받는다.
+/// 읽을 수 없는 문서는 progress에 `Event::DocumentSkipped`를 보내고 건너뛴다.
+fn read_source_file_or_skip(
+    path: PathBuf,
+    file: usize,
+    document_ids: &DocumentIds,
+    invalid_utf8: InvalidUtf8,
+    progress: &dyn Progress,
+) -> Option<SourceFile> {
+    match read_source_file(path.clone(), invalid_utf8) {
+        Ok(source) => {
+            let ids = document_ids.assign(file, source.records.len());
+            // 파일 수는 훑은 뒤에 보냈으므로 레코드가 여럿인 파일만 늘어난 만큼 더 보낸다.
+            if ids.len() > 1 {
+                progress.event(Event::Started {
+                    documents: ids.len() - 1,
+                });
             }
+            Some(SourceFile { ids, ..source })
+        }
+        Err(e) => {
+            // 읽을 수 없는 파일도 차례는 넘겨야 다음 파일이 번호를 받는다.
+            document_ids.assign(file, 0);
+            progress.event(Event::DocumentSkipped {
+                path,
+                reason: e.to_string(),
+            });
+            None
+        }
+    }
+}
 
-            Ok(filenames)
+/// 파일의 문서들을 정해둔 번호로 색인하고 문서 테이블에 넣을 정보를 함께 돌려준다.
+/// 해석할 수 없는 레코드는 progress에 `Event::DocumentSkipped`를 보내고 그 번호는 비워둔다.
+fn index_source_file(
+    source: SourceFile,
+    analyzer: &Pipeline,
+    progress: &dyn Progress,
+) -> (Vec<Document>, InMemoryIndex) {
+    let mut index = InMemoryIndex::new();
+    let documents = source
+        .ids
+        .zip(source.records)
+        .filter_map(|(doc_id, record)| {
+            let fields = match record.fields {
+                Ok(fields) => fields,
+                Err(reason) => {
+                    progress.event(Event::DocumentSkipped {
+                        path: source.path.clone(),
+                        reason: format!("line {}: {}", record.line.unwrap_or(0), reason),
+                    });
+                    return None;
+                }
+            };
+            let record_index = InMemoryIndex::from_fields(doc_id, &fields, analyzer);
+            let document = Document {
+                id: doc_id as u32,
+                path: source.path.clone(),
+                record: record.line,
+                offset: record.offset,
+                len: source.len,
+                word_count: record_index.word_count as u64,
+                mtime: source.mtime,
+            };
+            progress.event(Event::DocumentIndexed {
+                document_id: document.id,
+                bytes: record.len,
+                words: document.word_count,
+            });
+            index.merge(record_index);
+            Some(document)
         })
+        .collect();
+    (documents, index)
 }
 
 /// 싱글스레드에서 역인덱스를 생성한다.
-fn run_single_threaded(documents: Vec<PathBuf>, output_dir: PathBuf) -> Result<(), io::Error> {
+/// 문서 번호는 테이블의 다음 번호부터 `DocumentIds`가 파일 순서대로 매긴다.
+/// 읽을 수 없는 문서는 건너뛴다.
+/// 색인한 문서를 더한 테이블을 돌려준다.
+fn run_single_threaded(
+    documents: Vec<PathBuf>,
+    mut tmp_dir: TmpDir,
+    mut documents_table: DocumentTable,
+    mut merge: FileMerge,
+    analyzer: &Pipeline,
+    args: &Args,
+    progress: &dyn Progress,
+) -> Result<DocumentTable, Error> {
     let mut accumulated_index = InMemoryIndex::new();
-    let mut merge = FileMerge::new(&output_dir);
-    let mut tmp_dir = TmpDir::new(&output_dir);
+    let invalid_utf8 = args.invalid_utf8();
+    let document_ids = DocumentIds::new(documents_table.next_id());
 
     documents
         .into_iter()
-        .map(|filename| {
-            let mut f = File::open(filename)?;
-            let mut text = String::new();
-            f.read_to_string(&mut text)?;
-            <Result<String, io::Error>>::Ok(text)
-        })
         .enumerate()
-        .try_for_each(|(doc_id, text_result)| {
-            let index = InMemoryIndex::from_single_document(doc_id, text_result?);
+        .filter_map(|(file, path)| {
+            read_source_file_or_skip(path, file, &document_ids, invalid_utf8, progress)
+        })
+        .try_for_each(|source| {
+            let (documents, index) = index_source_file(source, analyzer, progress);
+            documents_table.extend(documents);
             accumulated_index.merge(index);
-            if accumulated_index.is_large() {
+            if accumulated_index.is_large(args.memory_limit()) {
                 let file = write_index_to_tmp_file(
                     // 꼼수..
                     std::mem::replace(&mut accumulated_index, InMemoryIndex::new()),
                     &mut tmp_dir,
+                    analyzer.spec(),
+                    progress,
                 )?;
                 merge.add_file(file)?;
             }
-            <Result<(), io::Error>>::Ok(())
+            <Result<(), Error>>::Ok(())
         })?;
 
     if !accumulated_index.is_empty() {
-        let file = write_index_to_tmp_file(accumulated_index, &mut tmp_dir)?;
+        let file =
+            write_index_to_tmp_file(accumulated_index, &mut tmp_dir, analyzer.spec(), progress)?;
         merge.add_file(file)?;
     }
-    merge.finish()
+    merge.finish()?;
+    Ok(documents_table)
 }
 
 /// 파이프라인을 이용해서 실행한다.
-fn run_pipeline(documents: Vec<PathBuf>, output_dir: PathBuf) -> io::Result<()> {
-    let (texts, h1) = start_file_reader_thread(documents);
-    let (pints, h2) = start_file_indexing_thread(texts);
-    let (gallons, h3) = start_in_memory_merge_thread(pints);
-    let (files, h4) = start_index_writer_thread(gallons, &output_dir);
-    let result = merge_index_files(files, &output_dir);
-
-    let r1 = h1.join().unwrap();
-    h2.join().unwrap();
-    h3.join().unwrap();
+/// 읽기, 색인, 메모리 병합 단계는 각각 jobs개의 스레드로 실행한다.
+/// 문서 번호는 `run_single_threaded`와 같이 `DocumentIds`가 매기므로 스레드 수와 관계없다.
+fn run_pipeline(
+    documents: Vec<PathBuf>,
+    tmp_dir: TmpDir,
+    mut documents_table: DocumentTable,
+    merge: FileMerge,
+    analyzer: Arc<Pipeline>,
+    args: &Args,
+    progress: SharedProgress,
+) -> Result<DocumentTable, Error> {
+    let spec = analyzer.spec().to_string();
+    let jobs = args.jobs();
+    let document_ids = DocumentIds::new(documents_table.next_id());
+    let (texts, h1) = start_file_reader_threads(
+        documents,
+        document_ids,
+        args.invalid_utf8(),
+        jobs,
+        progress.clone(),
+    );
+    let (pints, h2) = start_file_indexing_threads(texts, analyzer, jobs, progress.clone());
+    let (gallons, h3) = start_in_memory_merge_threads(pints, jobs, args.memory_limit());
+    let (files, h4) = start_index_writer_thread(gallons, tmp_dir, spec, progress);
+    let result = merge_index_files(files, merge);
+
+    h1.into_iter().for_each(|h| h.join().unwrap());
+    documents_table.extend(h2.into_iter().flat_map(|h| h.join().unwrap()));
+    h3.into_iter().for_each(|h| h.join().unwrap());
     let r4 = h4.join().unwrap();
 
-    r1?;
     r4?;
 
-    result
+    result?;
+    Ok(documents_table)
+}
+
+/// 여러 스레드가 나눠서 받는 Receiver
+type SharedReceiver<T> = Arc<Mutex<Receiver<T>>>;
+
+/// 다른 스레드와 번갈아 가며 받는다. 보내는 쪽이 모두 끝나면 멈춘다.
+fn shared_iter<T>(receiver: &SharedReceiver<T>) -> impl Iterator<Item = T> + '_ {
+    iter::from_fn(move || receiver.lock().unwrap().recv().ok())
 }
 
 /// 파일 시스템의 문서를 메모리로 로드한다.
-fn start_file_reader_thread(
+/// 스레드들이 documents를 앞에서부터 하나씩 가져가서 읽고, document_ids에서 받은 번호를 함께 보낸다.
+/// 읽을 수 없는 문서는 건너뛰므로 파이프라인을 멈추지 않는다.
+fn start_file_reader_threads(
     documents: Vec<PathBuf>,
-) -> (Receiver<String>, JoinHandle<Result<(), io::Error>>) {
+    document_ids: DocumentIds,
+    invalid_utf8: InvalidUtf8,
+    jobs: usize,
+    progress: SharedProgress,
+) -> (Receiver<SourceFile>, Vec<JoinHandle<()>>) {
     let (sender, receiver) = mpsc::sync_channel(1000);
+    let documents = Arc::new(documents);
+    let document_ids = Arc::new(document_ids);
+    let next = Arc::new(AtomicUsize::new(0));
 
-    let handle = thread::spawn(move || {
-        documents
-            .into_iter()
-            .map_while(|filename| {
-                let mut f = match File::open(filename) {
-                    Ok(f) => f,
-                    Err(e) => return Some(Err(e)),
-                };
-
-                let mut text = String::new();
-                if let Err(e) = f.read_to_string(&mut text) {
-                    return Some(Err(e));
-                }
-
-                if sender.send(text).is_err() {
-                    return None;
-                };
-                Some(Ok(()))
+    let handles = (0..jobs)
+        .map(|_| {
+            let (sender, documents, document_ids, next, progress) = (
+                sender.clone(),
+                documents.clone(),
+                document_ids.clone(),
+                next.clone(),
+                progress.clone(),
+            );
+            thread::spawn(move || {
+                let _ = iter::from_fn(|| {
+                    let file = next.fetch_add(1, Ordering::Relaxed);
+                    documents.get(file).map(|path| (file, path))
+                })
+                .filter_map(|(file, path)| {
+                    read_source_file_or_skip(
+                        path.clone(),
+                        file,
+                        &document_ids,
+                        invalid_utf8,
+                        progress.as_ref(),
+                    )
+                })
+                .try_for_each(|source| sender.send(source));
             })
-            .try_for_each(|r| r)
-    });
+        })
+        .collect();
 
-    (receiver, handle)
+    (receiver, handles)
 }
 
-fn start_file_indexing_thread(
-    texts: Receiver<String>,
-) -> (Receiver<InMemoryIndex>, JoinHandle<()>) {
+/// 문서의 인덱스를 만든다.
+/// 스레드가 끝나면 색인한 문서의 테이블 행을 돌려준다.
+fn start_file_indexing_threads(
+    sources: Receiver<SourceFile>,
+    analyzer: Arc<Pipeline>,
+    jobs: usize,
+    progress: SharedProgress,
+) -> (Receiver<InMemoryIndex>, Vec<JoinHandle<Vec<Document>>>) {
     let (sender, receiver) = mpsc::sync_channel(1000);
+    let sources = Arc::new(Mutex::new(sources));
 
-    let handle = thread::spawn(move || {
-        texts
-            .into_iter()
-            .enumerate()
-            .try_for_each(|(doc_id, text)| {
-                let index = InMemoryIndex::from_single_document(doc_id, text);
-                if sender.send(index).is_err() {
-                    return ControlFlow::Break(());
-                }
-                ControlFlow::Continue(())
-            });
-    });
-    (receiver, handle)
+    let handles = (0..jobs)
+        .map(|_| {
+            let (sender, sources, analyzer, progress) = (
+                sender.clone(),
+                sources.clone(),
+                analyzer.clone(),
+                progress.clone(),
+            );
+            thread::spawn(move || {
+                let mut documents = Vec::new();
+                let _ = shared_iter(&sources).try_for_each(|source| {
+                    let (indexed, index) = index_source_file(source, &analyzer, progress.as_ref());
+                    documents.extend(indexed);
+                    sender.send(index)
+                });
+                documents
+            })
+        })
+        .collect();
+    (receiver, handles)
 }
 
-fn start_in_memory_merge_thread(
+/// 문서의 인덱스를 모아서 큰 인덱스를 만든다.
+/// 스레드마다 따로 모으므로 한 인덱스의 문서 번호는 띄엄띄엄할 수 있다.
+/// 모든 스레드가 모은 인덱스의 합이 memory_limit를 넘지 않도록 스레드마다 나눠서 쓴다.
+fn start_in_memory_merge_threads(
     file_indexes: Receiver<InMemoryIndex>,
-) -> (Receiver<InMemoryIndex>, JoinHandle<()>) {
+    jobs: usize,
+    memory_limit: usize,
+) -> (Receiver<InMemoryIndex>, Vec<JoinHandle<()>>) {
     let (sender, receiver) = mpsc::sync_channel(1000);
-    let handle = spawn(move || {
-        let flow =
-            file_indexes
-                .into_iter()
-                .try_fold(InMemoryIndex::new(), |mut accumulated_index, fi| {
-                    accumulated_index.merge(fi);
-                    if accumulated_index.is_large() {
-                        if sender.send(accumulated_index).is_err() {
-                            return ControlFlow::Break(());
+    let memory_limit = memory_limit / jobs;
+    let file_indexes = Arc::new(Mutex::new(file_indexes));
+
+    let handles = (0..jobs)
+        .map(|_| {
+            let (sender, file_indexes) = (sender.clone(), file_indexes.clone());
+            spawn(move || {
+                let flow = shared_iter(&file_indexes).try_fold(
+                    InMemoryIndex::new(),
+                    |mut accumulated_index, fi| {
+                        accumulated_index.merge(fi);
+                        if accumulated_index.is_large(memory_limit) {
+                            if sender.send(accumulated_index).is_err() {
+                                return ControlFlow::Break(());
+                            }
+                            accumulated_index = InMemoryIndex::new()
                         }
-                        accumulated_index = InMemoryIndex::new()
+                        ControlFlow::Continue(accumulated_index)
+                    },
+                );
+                if let ControlFlow::Continue(accumulated_index) = flow {
+                    if !accumulated_index.is_empty() {
+                        let _ = sender.send(accumulated_index);
                     }
-                    ControlFlow::Continue(accumulated_index)
-                });
-        if let ControlFlow::Continue(accumulated_index) = flow {
-            if !accumulated_index.is_empty() {
-                let _ = sender.send(accumulated_index);
-            }
-        }
-    });
+                }
+            })
+        })
+        .collect();
 
-    (receiver, handle)
+    (receiver, handles)
 }
 
 fn start_index_writer_thread(
     big_indexes: Receiver<InMemoryIndex>,
-    output_dir: &Path,
-) -> (Receiver<PathBuf>, JoinHandle<Result<(), io::Error>>) {
+    mut tmp_dir: TmpDir,
+    analyzer: String,
+    progress: SharedProgress,
+) -> (Receiver<PathBuf>, JoinHandle<Result<(), Error>>) {
     let (sender, reciever) = mpsc::sync_channel(1000);
-    let mut tmp_dir = TmpDir::new(output_dir);
     let handle = spawn(move || {
         big_indexes
             .into_iter()
             .map_while(move |index| {
-                let file = match write_index_to_tmp_file(index, &mut tmp_dir) {
+                let file = match write_index_to_tmp_file(
+                    index,
+                    &mut tmp_dir,
+                    &analyzer,
+                    progress.as_ref(),
+                ) {
                     Ok(file) => file,
                     Err(e) => return Some(Err(e)),
                 };
@@ -196,23 +416,520 @@ fn start_index_writer_thread(
     (reciever, handle)
 }
 
-fn merge_index_files(files: Receiver<PathBuf>, output_dir: &Path) -> io::Result<()> {
+fn merge_index_files(files: Receiver<PathBuf>, merge: FileMerge) -> Result<(), Error> {
     files
         .into_iter()
-        .try_fold(FileMerge::new(output_dir), |mut merge, file| {
+        .try_fold(merge, |mut merge, file| {
             merge.add_file(file)?;
-            <Result<FileMerge, io::Error>>::Ok(merge)
+            <Result<FileMerge, Error>>::Ok(merge)
         })?
         .finish()
 }
-pub fn run(args: Args) -> Result<(), io::Error> {
-    let output_dir = PathBuf::from(".");
-    let filenames = args.filename();
+
+/// 기존 인덱스에 문서를 더할 준비를 한다. 인덱스가 없으면 처음부터 만든다.
+/// 디스크에서 사라진 문서와 내용이 바뀐 문서는 지운 것으로 표시하고,
+/// 바뀌지 않은 문서와 delete로 지운 경로의 문서는 다시 색인하지 않도록 documents에서 뺀다.
+/// 기존 인덱스는 merge에 넣어서 새 문서와 함께 병합한다.
+fn open_existing_index(
+    index_path: &Path,
+    mut merge: FileMerge,
+    documents: &mut Vec<PathBuf>,
+) -> Result<(DocumentTable, FileMerge, Tombstones), Error> {
+    if !index_path.exists() {
+        return Ok((DocumentTable::new(), merge, Tombstones::new()));
+    }
+
+    let documents_table = DocumentTable::open(documents_path(index_path))?;
+    let mut tombstones = Tombstones::open(tombstones_path(index_path))?;
+    let requested = documents.iter().collect::<HashSet<_>>();
+    let mut unchanged = HashSet::new();
+    documents_table.iter().try_for_each(|document| {
+        if tombstones.contains(document.id) {
+            return Ok(());
+        }
+        match document.path.metadata() {
+            Ok(metadata)
+                if metadata.len() == document.len
+                    && mtime_of(metadata.modified()?) == document.mtime =>
+            {
+                unchanged.insert(document.path.clone());
+            }
+            // 바뀌었지만 이번에 색인하지 않는 문서는 그대로 둔다.
+            Ok(_) if !requested.contains(&document.path) => {}
+            Ok(_) => {
+                tombstones.insert(document.id);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tombstones.insert(document.id);
+            }
+            Err(e) => return Err(e).with_path(&document.path),
+        }
+        Ok(())
+    })?;
+    let excluded = ExcludedPaths::open(excluded_path(index_path))?;
+    documents.retain(|path| !unchanged.contains(path) && !excluded.contains(path));
+
+    merge.add_existing_index(tombstones.clone())?;
+    Ok((documents_table, merge, tombstones))
+}
+
+/// 색인에 쓸 분석기를 정한다.
+/// 기존 인덱스에 더할 때는 인덱스에 기록된 분석기를 쓰고, 다른 분석기를 지정하면 오류를 돌려준다.
+/// 설정 문자열은 정규화해서 비교하므로 공백이나 쓰는 방식만 다르면 같은 분석기로 본다.
+fn choose_analyzer(args: &Args, index_path: &Path) -> Result<Pipeline, Error> {
+    let existing = if args.is_append() && index_path.exists() {
+        let mut f = File::open(index_path).with_path(index_path)?;
+        let spec = Header::read(&mut f).with_path(index_path)?.analyzer;
+        Some(Pipeline::from_spec(&spec).with_path(index_path)?)
+    } else {
+        None
+    };
+    let requested = args.analyzer().map(Pipeline::from_spec).transpose()?;
+    match (existing, requested) {
+        (Some(existing), Some(requested)) if existing.spec() != requested.spec() => {
+            Err(Error::InvalidArgument(format!(
+                "index was built with analyzer {:?}, cannot append with {:?}",
+                existing.spec(),
+                requested.spec()
+            )))
+        }
+        (Some(existing), _) => Ok(existing),
+        (None, Some(requested)) => Ok(requested),
+        (None, None) => Pipeline::from_spec(DEFAULT_ANALYZER),
+    }
+}
+
+/// 문서를 색인해서 --output 디렉터리에 인덱스 파일과 문서 테이블을 쓴다.
+/// 진행 상황은 출력하지 않고 progress로 보낸다.
+/// 실패하면 --tmp-dir에 만든 임시 파일을 지운다.
+pub fn run(args: Args, progress: SharedProgress) -> Result<(), Error> {
+    let tmp_dir = TmpDir::new(args.tmp_dir());
+    let result = build_index(&args, tmp_dir.clone(), progress);
+    if result.is_err() {
+        let _ = tmp_dir.cleanup();
+    }
+    result
+}
+
+fn build_index(args: &Args, tmp_dir: TmpDir, progress: SharedProgress) -> Result<(), Error> {
+    let output_dir = PathBuf::from(args.output());
+    fs::create_dir_all(&output_dir).with_path(&output_dir)?;
+    fs::create_dir_all(args.tmp_dir()).with_path(args.tmp_dir())?;
+    let index_path = output_dir.join(args.name());
     let single_threaded = args.is_single_threaded();
-    let documents = expand_filename_arguments(filenames)?;
-    if single_threaded {
-        run_single_threaded(documents, output_dir)
+    let analyzer = choose_analyzer(args, &index_path)?;
+    let mut documents = expand_filename_arguments(args, progress.as_ref())?;
+    let merge = FileMerge::new(
+        &index_path,
+        tmp_dir.clone(),
+        args.merge_fan_in(),
+        progress.clone(),
+    );
+    let (documents_table, merge, tombstones) = if args.is_append() {
+        open_existing_index(&index_path, merge, &mut documents)?
+    } else {
+        (DocumentTable::new(), merge, Tombstones::new())
+    };
+    progress.event(Event::Started {
+        documents: documents.len(),
+    });
+    let mut documents_table = if single_threaded {
+        run_single_threaded(
+            documents,
+            tmp_dir,
+            documents_table,
+            merge,
+            &analyzer,
+            args,
+            progress.as_ref(),
+        )?
     } else {
-        run_pipeline(documents, output_dir)
+        run_pipeline(
+            documents,
+            tmp_dir,
+            documents_table,
+            merge,
+            Arc::new(analyzer),
+            args,
+            progress,
+        )?
+    };
+
+    // 병합하면서 인덱스에서 지운 문서는 테이블에서도 뺀다.
+    tombstones.iter().for_each(|id| {
+        documents_table.remove(id);
+    });
+    documents_table.write(documents_path(&index_path))?;
+    // 병합하면서 지운 문서를 실제로 지웠다. 새로 만든 인덱스는 지운 경로도 잊는다.
+    let mut stale = vec![tombstones_path(&index_path)];
+    if !args.is_append() {
+        stale.push(excluded_path(&index_path));
+    }
+    stale
+        .into_iter()
+        .try_for_each(|path| match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e).with_path(path),
+            _ => Ok(()),
+        })
+}
+
+/// 문서를 지운 것으로 표시한다.
+/// 인덱스는 다음에 --append로 병합할 때 실제로 지운다.
+/// 지운 경로는 기록해두고 그 뒤의 --append도 다시 색인하지 않는다.
+pub fn delete(args: DeleteArgs) -> Result<(), Error> {
+    let index_path = Path::new(args.index());
+    let documents_table = DocumentTable::open(documents_path(index_path))?;
+    let deleted_path = tombstones_path(index_path);
+    let mut tombstones = Tombstones::open(&deleted_path)?;
+    let excluded_path = excluded_path(index_path);
+    let mut excluded = ExcludedPaths::open(&excluded_path)?;
+    args.paths().iter().for_each(|path| {
+        excluded.insert(path);
+    });
+
+    let deleted = documents_table
+        .iter()
+        .filter(|document| {
+            args.paths()
+                .iter()
+                .any(|path| document.path.starts_with(path))
+        })
+        .filter(|document| tombstones.insert(document.id))
+        .count();
+    tombstones.write(&deleted_path)?;
+    excluded.write(&excluded_path)?;
+    println!("deleted {} documents", deleted);
+
+    Ok(())
+}
+
+/// 병합된 인덱스 파일에서 질의에 맞는 문서를 찾아 출력한다.
+/// 인덱스 파일 옆에 문서 테이블이 있으면 문서 번호 대신 경로를 출력한다.
+pub fn search(args: SearchArgs) -> Result<(), Error> {
+    let index_path = Path::new(args.index());
+    let searcher = IndexSearcher::open(index_path)?;
+    let table_path = documents_path(index_path);
+    let documents_table = if table_path.exists() {
+        DocumentTable::open(table_path)?
+    } else {
+        DocumentTable::new()
+    };
+
+    let query = Query::parse(args.query())?;
+    let postings = query.evaluate(&searcher)?;
+    println!("{}: {} documents", args.query(), postings.len());
+    let name = |document_id: u32| match documents_table.get(document_id) {
+        Some(document) => document.name(),
+        None => format!("document {}", document_id),
+    };
+
+    let highlighter = Highlighter {
+        pre: "[".to_string(),
+        post: "]".to_string(),
+        ..Highlighter::default()
+    };
+    let terms = query.terms(&searcher)?;
+    let print_snippets = |document_id: u32| {
+        if !args.is_snippets() {
+            return Ok(());
+        }
+        let Some(document) = documents_table.get(document_id) else {
+            return Ok(());
+        };
+        let positions = term_positions(&searcher, &terms, document_id)?;
+        match highlighter.highlight_document(
+            document,
+            searcher.analyzer(),
+            &positions,
+            InvalidUtf8::Lossy,
+        ) {
+            Ok(snippets) => snippets
+                .iter()
+                .for_each(|snippet| println!("      {}: {}", snippet.field, snippet.text)),
+            Err(e) => println!("      (cannot read {}: {})", document.path.display(), e),
+        }
+        <Result<(), Error>>::Ok(())
+    };
+
+    match args.top() {
+        Some(k) => Bm25::default()
+            .rank(&searcher, &documents_table, &terms, &postings, k)?
+            .iter()
+            .try_for_each(|scored| {
+                println!("  {:.4} {}", scored.score, name(scored.document_id));
+                print_snippets(scored.document_id)
+            })?,
+        None => postings.iter().try_for_each(|posting| {
+            println!(
+                "  {}: positions {:?}",
+                name(posting.document_id),
+                posting.positions
+            );
+            print_snippets(posting.document_id)
+        })?,
+    }
+
+    Ok(())
+}
+
+/// 인덱스 파일의 헤더와 테이블을 요약해서 출력한다.
+/// --verify를 주면 대신 파일 전체를 확인해서 찾은 문제를 모두 출력하고, 문제가 있으면 첫 문제를 오류로 돌려준다.
+pub fn inspect(args: InspectArgs) -> Result<(), Error> {
+    let index_path = Path::new(args.index());
+    let info = IndexFileInfo::open(index_path)?;
+
+    if args.is_verify() {
+        let problems = info.verify();
+        problems
+            .iter()
+            .for_each(|problem| println!("  {}", problem));
+        return match problems.into_iter().next() {
+            Some(problem) => Err(Error::from(problem.reason).with_path(index_path)),
+            None => {
+                println!(
+                    "{}: ok, {} entries verified",
+                    index_path.display(),
+                    info.entries().len()
+                );
+                Ok(())
+            }
+        };
+    }
+
+    let header = info.header();
+    println!("{}", index_path.display());
+    println!("  format version  {}", FORMAT_VERSION);
+    println!("  analyzer        {}", header.analyzer);
+    println!("  file size       {} bytes", info.file_len());
+    println!("  header          {} bytes", header.size());
+    println!(
+        "  postings        {} bytes (offset {}..{}, crc {:08x})",
+        header.contents_offset - header.size(),
+        header.size(),
+        header.contents_offset,
+        header.main_crc
+    );
+    println!(
+        "  contents        {} bytes (offset {}..{}, crc {:08x})",
+        info.contents_len(),
+        header.contents_offset,
+        info.file_len(),
+        header.contents_crc
+    );
+    println!(
+        "  terms           {} (header says {})",
+        info.entries().len(),
+        header.term_count
+    );
+    if let Some(sizes) = info.posting_sizes() {
+        println!(
+            "  posting lists   min {} / median {} / max {} bytes, {:.1} bytes on average",
+            sizes.min,
+            sizes.median,
+            sizes.max,
+            sizes.total as f64 / info.entries().len() as f64
+        );
+    }
+
+    let top = info.top_terms(args.top());
+    if !top.is_empty() {
+        println!("top {} terms by df", top.len());
+        println!("  {:>8} {:>10}  term", "df", "bytes");
+        top.iter()
+            .for_each(|entry| println!("  {:>8} {:>10}  {}", entry.df, entry.nbytes, entry.term));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        parse_args::{parse_args_from, Command},
+        progress::NoProgress,
+        terms::{terms_path, TermDictionary},
+        test_util::TestDir,
+    };
+
+    /// corpus를 dir/index.dat로 색인한다. extra는 더 줄 옵션이다.
+    fn index(dir: &TestDir, extra: &[&str]) {
+        index_with(dir, extra, Arc::new(NoProgress)).unwrap();
+    }
+
+    fn index_with(dir: &TestDir, extra: &[&str], progress: SharedProgress) -> Result<(), Error> {
+        let (output, tmp, corpus) = (dir.join(""), dir.join("tmp"), dir.join("corpus"));
+        let args = ["fingertips", "-o", output.to_str().unwrap()]
+            .into_iter()
+            .chain(["--tmp-dir", tmp.to_str().unwrap()])
+            .chain(extra.iter().copied())
+            .chain([corpus.to_str().unwrap()]);
+        match parse_args_from(args) {
+            Command::Index(args) => run(args, progress),
+            command => panic!("expected index command, got {:?}", command),
+        }
+    }
+
+    /// 질의에 맞는 문서의 corpus 안의 파일 이름
+    fn search_names(dir: &TestDir, q: &str) -> Vec<String> {
+        let index_path = dir.join("index.dat");
+        let searcher = IndexSearcher::open(&index_path).unwrap();
+        let table = DocumentTable::open(documents_path(&index_path)).unwrap();
+        Query::parse(q)
+            .unwrap()
+            .evaluate(&searcher)
+            .unwrap()
+            .iter()
+            .map(|posting| {
+                let document = table.get(posting.document_id).unwrap();
+                let name = document.path.file_name().unwrap();
+                name.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    fn write_corpus(dir: &TestDir, files: &[(&str, &str)]) {
+        fs::create_dir_all(dir.join("corpus")).unwrap();
+        files.iter().for_each(|(name, text)| {
+            fs::write(dir.join("corpus").join(name), text).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_delete_then_append() {
+        let dir = TestDir::new("run-delete");
+        write_corpus(&dir, &[("a.txt", "gap buffer"), ("b.txt", "gap rope")]);
+        index(&dir, &[]);
+        assert_eq!(search_names(&dir, "gap"), ["a.txt", "b.txt"]);
+
+        let deleted = dir.join("corpus").join("b.txt");
+        let index_path = dir.join("index.dat");
+        let delete_args = [
+            "fingertips",
+            "delete",
+            "-i",
+            index_path.to_str().unwrap(),
+            deleted.to_str().unwrap(),
+        ];
+        match parse_args_from(delete_args) {
+            Command::Delete(args) => delete(args).unwrap(),
+            command => panic!("expected delete command, got {:?}", command),
+        }
+        assert_eq!(search_names(&dir, "gap"), ["a.txt"]);
+
+        // 디렉터리를 다시 훑어도 지운 파일은 돌아오지 않는다.
+        index(&dir, &["--append"]);
+        assert_eq!(search_names(&dir, "gap"), ["a.txt"]);
+        index(&dir, &["--append"]);
+        assert_eq!(search_names(&dir, "gap"), ["a.txt"]);
+
+        // 새로 만들면 지운 경로도 잊는다.
+        index(&dir, &[]);
+        assert_eq!(search_names(&dir, "gap"), ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_append_changed_removed_and_new_files() {
+        let dir = TestDir::new("run-append");
+        write_corpus(
+            &dir,
+            &[
+                ("kept.txt", "gap kept"),
+                ("changed.txt", "gap old"),
+                ("removed.txt", "gap removed"),
+            ],
+        );
+        index(&dir, &[]);
+        let index_path = dir.join("index.dat");
+        let tmp_files = || fs::read_dir(dir.join("tmp")).map_or(0, |d| d.count());
+
+        fs::write(dir.join("corpus").join("changed.txt"), "gap new text").unwrap();
+        fs::remove_file(dir.join("corpus").join("removed.txt")).unwrap();
+        write_corpus(&dir, &[("new.txt", "gap new")]);
+        index(&dir, &["--append"]);
+
+        assert_eq!(
+            search_names(&dir, "gap"),
+            ["kept.txt", "changed.txt", "new.txt"]
+        );
+        assert_eq!(search_names(&dir, "kept"), ["kept.txt"]);
+        assert!(search_names(&dir, "old").is_empty());
+        assert!(search_names(&dir, "removed").is_empty());
+        assert_eq!(search_names(&dir, "new"), ["changed.txt", "new.txt"]);
+        assert_eq!(tmp_files(), 0);
+
+        // 단어 사전은 바뀐 인덱스와 맞는다.
+        let header = Header::read(&mut File::open(&index_path).unwrap()).unwrap();
+        let terms = TermDictionary::open(terms_path(&index_path), header.contents_crc).unwrap();
+        assert_eq!(terms.unwrap().len() as u64, header.term_count);
+
+        // 바뀐 것이 없어도 기존 인덱스는 남는다.
+        index(&dir, &["--append"]);
+        assert_eq!(
+            search_names(&dir, "gap"),
+            ["kept.txt", "changed.txt", "new.txt"]
+        );
+        assert_eq!(tmp_files(), 0);
+    }
+
+    #[test]
+    fn test_append_compares_normalized_analyzers() {
+        let dir = TestDir::new("run-analyzer");
+        write_corpus(&dir, &[("a.txt", "gap buffer")]);
+        index(&dir, &["--analyzer", "lowercase, ngram:2-2"]);
+
+        write_corpus(&dir, &[("b.txt", "gap rope")]);
+        index(&dir, &["--append", "--analyzer", "lowercase,ngram:2"]);
+        assert_eq!(search_names(&dir, "ga"), ["a.txt", "b.txt"]);
+
+        let result = index_with(
+            &dir,
+            &["--append", "--analyzer", "lowercase"],
+            Arc::new(NoProgress),
+        );
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_started_once_with_file_count() {
+        let dir = TestDir::new("run-started");
+        write_corpus(
+            &dir,
+            &[("a.txt", "gap"), ("b.txt", "rope"), ("c.txt", "piece")],
+        );
+        let (sender, receiver) = std::sync::mpsc::channel();
+        index_with(&dir, &[], Arc::new(sender)).unwrap();
+        let started = receiver
+            .try_iter()
+            .filter(|event| matches!(event, Event::Started { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(started, [Event::Started { documents: 3 }]);
+    }
+
+    #[test]
+    fn test_document_ids_do_not_depend_on_jobs() {
+        let dir = TestDir::new("run-jobs");
+        let files: Vec<(String, String)> = (0..50)
+            .map(|i| (format!("{:02}.txt", i), format!("gap buffer {}", i)))
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(n, t)| (&n[..], &t[..])).collect();
+        write_corpus(&dir, &files);
+
+        let ids = |extra: &[&str]| {
+            index(&dir, extra);
+            let table = DocumentTable::open(documents_path(&dir.join("index.dat"))).unwrap();
+            let ids: Vec<(u32, PathBuf)> = table
+                .iter()
+                .map(|document| (document.id, document.path.clone()))
+                .collect();
+            (ids, search_names(&dir, "gap"))
+        };
+        let expected = ids(&["--single-threaded"]);
+        assert_eq!(expected.0.len(), 50);
+        assert_eq!(expected.0[7], (7, dir.join("corpus").join("07.txt")));
+        assert_eq!(ids(&["-j1"]), expected);
+        assert_eq!(ids(&["-j8"]), expected);
     }
 }
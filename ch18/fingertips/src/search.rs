@@ -0,0 +1,265 @@
+use std::{fs::File, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{
+    analysis::{Analyzer, Pipeline},
+    error::{Error, PathContext},
+    field::Field,
+    header::{FormatError, Header, Section},
+    index::{decode_hits_in, split_hits, Posting, RawHit},
+    query::phrase_at,
+    read::{Entry, IndexFileReader},
+    terms::{terms_path, TermDictionary},
+    tombstone::{tombstones_path, Tombstones},
+};
+
+/// 병합된 인덱스 파일에서 단어를 찾는 읽기 전용 Reader
+/// 파일을 메모리에 매핑하고 테이블만 따로 읽어둔다. Posting 목록은 복사하지 않고 매핑된 영역을 그대로 빌려준다.
+/// `IndexFileReader`와 달리 아무 단어나 찾을 수 있고 파일을 삭제하지 않는다.
+/// 찾는 동안 상태를 바꾸지 않으므로 여러 스레드에서 함께 쓸 수 있다.
+pub struct IndexSearcher {
+    /// 매핑한 인덱스 파일 전체
+    mmap: Mmap,
+    /// 단어 순으로 정렬된 테이블
+    entries: Vec<Entry>,
+    /// 단어에서 테이블의 순번을 찾는 사전
+    terms: TermDictionary,
+    /// 검색 결과에서 뺄 문서
+    tombstones: Tombstones,
+    /// 색인할 때와 같은 분석기
+    analyzer: Pipeline,
+}
+
+impl IndexSearcher {
+    /// 인덱스 파일을 매핑하고 테이블을 읽는다.
+    /// 인덱스 파일 옆에 삭제 표시 파일이 있으면 함께 읽는다.
+    /// 헤더가 맞지 않거나 테이블의 체크섬이 다르면 `Error::CorruptIndex`를 돌려준다.
+    /// 열 때마다 파일 전체를 읽지 않도록 인덱스 데이터의 체크섬은 검사하지 않는다. 전체 검사는 `inspect --verify`로 한다.
+    /// 질의는 헤더에 기록된 분석기로 나눈다.
+    /// 단어 사전 파일이 없거나 인덱스와 맞지 않으면 테이블로 사전을 새로 만든다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexSearcher, Error> {
+        IndexSearcher::open_at(filename.as_ref()).with_path(filename)
+    }
+
+    fn open_at(filename: &Path) -> Result<IndexSearcher, Error> {
+        let mut file = File::open(filename)?;
+        let header = Header::read_bounded(&mut file)?;
+        let analyzer = Pipeline::from_spec(&header.analyzer)?;
+
+        // 인덱스는 새 파일을 만든 뒤에 이름을 바꿔서 교체하므로(`merge::move_file` 참고), 매핑한 파일의 내용은 바뀌지 않는다.
+        let mmap = unsafe { Mmap::map(&file)? };
+        if (mmap.len() as u64) < header.contents_offset {
+            return Err(FormatError::Truncated {
+                expected: header.contents_offset,
+                actual: mmap.len() as u64,
+            }
+            .into());
+        }
+
+        let mut contents = &mmap[header.contents_offset as usize..];
+        if crc32fast::hash(contents) != header.contents_crc {
+            return Err(FormatError::ChecksumMismatch(Section::Contents).into());
+        }
+        let mut entries = Vec::new();
+        while let Some(entry) = IndexFileReader::read_entry(&mut contents)? {
+            let end = entry.offset.checked_add(entry.nbytes);
+            if entry.offset < header.size() || end.is_none_or(|end| end > header.contents_offset) {
+                return Err(FormatError::Truncated {
+                    expected: end.unwrap_or(u64::MAX),
+                    actual: header.contents_offset,
+                }
+                .into());
+            }
+            entries.push(entry);
+        }
+        if entries.len() as u64 != header.term_count {
+            return Err(FormatError::TermCountMismatch {
+                expected: header.term_count,
+                actual: entries.len() as u64,
+            }
+            .into());
+        }
+
+        let terms = match TermDictionary::open(terms_path(filename), header.contents_crc)? {
+            Some(terms) if terms.len() == entries.len() => terms,
+            _ => TermDictionary::build(entries.iter().map(|entry| entry.term.as_str()))?,
+        };
+
+        let tombstones = Tombstones::open(tombstones_path(filename))?;
+
+        Ok(IndexSearcher {
+            mmap,
+            entries,
+            terms,
+            tombstones,
+            analyzer,
+        })
+    }
+
+    /// 색인할 때 쓴 분석기
+    pub fn analyzer(&self) -> &Pipeline {
+        &self.analyzer
+    }
+
+    /// 인덱스에 있는 단어의 수
+    pub fn term_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 단어 순으로 정렬된 테이블
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// 단어 사전
+    pub fn terms(&self) -> &TermDictionary {
+        &self.terms
+    }
+
+    /// 사전에서 단어의 Entry를 찾는다.
+    pub fn entry(&self, term: &str) -> Option<&Entry> {
+        self.terms.get(term).map(|i| self.entry_at(i))
+    }
+
+    /// 사전이 돌려준 순번의 Entry
+    pub fn entry_at(&self, i: u64) -> &Entry {
+        &self.entries[i as usize]
+    }
+
+    /// 단어의 인덱스 데이터를 복사하지 않고 빌려준다. 지운 문서도 들어 있다.
+    /// 구조는 `index::PostingListBuilder` 참고
+    pub fn posting_bytes(&self, term: &str) -> Option<&[u8]> {
+        self.entry(term)
+            .map(|entry| &self.mmap[entry.offset as usize..(entry.offset + entry.nbytes) as usize])
+    }
+
+    /// 단어의 Hit를 위치를 디코딩하지 않고 돌려준다. 지운 문서는 빼고 돌려준다.
+    pub fn raw_hits(&self, term: &str) -> Result<Vec<RawHit<'_>>, Error> {
+        let mut hits = match self.posting_bytes(term) {
+            Some(buf) => split_hits(buf)?,
+            None => return Ok(Vec::new()),
+        };
+        hits.retain(|hit| !self.tombstones.contains(hit.document_id));
+        Ok(hits)
+    }
+
+    /// 텍스트가 나온 문서와 위치를 돌려준다. 지운 문서는 빼고 돌려준다.
+    /// 색인할 때와 같은 분석기로 텍스트를 나누고, 토큰이 여럿이면 그 위치 차이대로 붙어 있는 곳을 찾는다.
+    /// 결과의 위치는 첫 토큰의 위치이다. 토큰이 하나도 남지 않으면(불용어 등) 빈 목록을 돌려준다.
+    pub fn search(&self, text: &str) -> Result<Vec<Posting>, Error> {
+        self.search_in(text, None)
+    }
+
+    /// `search`와 같지만 field가 있으면 그 필드에서만 찾는다.
+    pub fn search_in(&self, text: &str, field: Option<Field>) -> Result<Vec<Posting>, Error> {
+        let tokens = self.analyzer.analyze(text);
+        let lists = tokens
+            .iter()
+            .map(|token| Ok((token.position, self.postings_in(&token.text, field)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(phrase_at(lists))
+    }
+
+    /// 분석하지 않은 단어 그대로 테이블에서 찾아서 문서와 위치를 돌려준다.
+    /// 지운 문서는 빼고 돌려준다.
+    pub fn postings(&self, term: &str) -> Result<Vec<Posting>, Error> {
+        self.postings_in(term, None)
+    }
+
+    /// `postings`와 같지만 field가 있으면 그 필드의 위치만 돌려준다.
+    pub fn postings_in(&self, term: &str, field: Option<Field>) -> Result<Vec<Posting>, Error> {
+        let mut postings = match self.posting_bytes(term) {
+            Some(buf) => decode_hits_in(buf, field)?,
+            None => return Ok(Vec::new()),
+        };
+        postings.retain(|posting| !self.tombstones.contains(posting.document_id));
+        Ok(postings)
+    }
+
+    /// 지운 것으로 표시된 문서
+    pub fn tombstones(&self) -> &Tombstones {
+        &self.tombstones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{build_index, TestDir};
+
+    #[test]
+    fn test_search_merged_index() {
+        let dir = TestDir::new("search");
+        let index_path = build_index(
+            &dir,
+            &Pipeline::default(),
+            &[
+                ("a.txt", "gap buffer"),
+                ("b.txt", "rope"),
+                ("c.txt", "piece table buffer"),
+            ],
+        );
+
+        let searcher = IndexSearcher::open(&index_path).unwrap();
+        assert_eq!(
+            searcher.search("buffer").unwrap(),
+            [
+                Posting {
+                    document_id: 0,
+                    positions: vec![1],
+                },
+                Posting {
+                    document_id: 2,
+                    positions: vec![2],
+                },
+            ]
+        );
+        assert_eq!(
+            searcher.search("piece table").unwrap(),
+            [Posting {
+                document_id: 2,
+                positions: vec![0],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_open_checks_only_header_and_table() {
+        let dir = TestDir::new("search-open");
+        let index_path = build_index(&dir, &Pipeline::default(), &[("a.txt", "gap buffer")]);
+        let mut bytes = std::fs::read(&index_path).unwrap();
+        let header = Header::read(&mut &bytes[..]).unwrap();
+
+        // 인덱스 데이터가 깨져도 열 때는 검사하지 않는다.
+        bytes[header.size() as usize] ^= 0xff;
+        std::fs::write(&index_path, &bytes).unwrap();
+        assert!(IndexSearcher::open(&index_path).is_ok());
+
+        // 더하면 넘치는 위치는 잘린 파일로 본다.
+        let contents = header.contents_offset as usize;
+        bytes[contents..][..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let contents_crc = crc32fast::hash(&bytes[contents..]);
+        bytes[32..36].copy_from_slice(&contents_crc.to_le_bytes());
+        std::fs::write(&index_path, &bytes).unwrap();
+        match IndexSearcher::open(&index_path) {
+            Err(Error::CorruptIndex {
+                reason: FormatError::Truncated { expected, .. },
+                ..
+            }) => assert_eq!(expected, u64::MAX),
+            Err(e) => panic!("expected truncated index, got {}", e),
+            Ok(_) => panic!("expected truncated index"),
+        }
+
+        // 테이블이 깨지면 체크섬으로 찾는다.
+        bytes[contents] ^= 0xff;
+        std::fs::write(&index_path, &bytes).unwrap();
+        assert!(matches!(
+            IndexSearcher::open(&index_path),
+            Err(Error::CorruptIndex {
+                reason: FormatError::ChecksumMismatch(Section::Contents),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    documents::{documents_path, DocumentTable},
+    error::Error,
+    extract::InvalidUtf8,
+    highlight::{term_positions, Highlighter, Snippet},
+    query::{Query, MAX_QUERY_DEPTH},
+    rank::Bm25,
+    search::IndexSearcher,
+    tombstone::tombstones_path,
+};
+
+/// 한 페이지에 돌려줄 결과 수의 기본값
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// 한 페이지에 돌려줄 결과 수의 최댓값
+pub const MAX_LIMIT: usize = 100;
+
+/// 질의 문자열의 최대 바이트 수
+pub const MAX_QUERY_LEN: usize = 4096;
+
+/// /search의 질의 인수
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    /// 질의. 문법은 `query::Query::parse` 참고
+    pub q: String,
+    /// 건너뛸 결과 수
+    #[serde(default)]
+    pub offset: usize,
+    /// 돌려줄 결과 수. MAX_LIMIT보다 크면 MAX_LIMIT을 쓴다.
+    pub limit: Option<usize>,
+}
+
+/// /search의 응답
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    /// 질의에 맞는 전체 문서 수
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// BM25 점수가 높은 순으로 정렬한 offset부터 limit개의 문서
+    pub results: Vec<SearchResult>,
+}
+
+/// 검색 결과의 문서 하나
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub document_id: u32,
+    /// 문서 테이블에 없는 문서는 None
+    pub path: Option<PathBuf>,
+    /// JSON-lines 파일의 레코드이면 그 줄
+    pub record: Option<u32>,
+    pub score: f64,
+    /// 질의에 맞는 토큰 위치
+    pub positions: Vec<u32>,
+    /// 원문에서 맞은 단어 주변. 파일을 읽을 수 없으면 비어 있다.
+    pub snippets: Vec<Snippet>,
+}
+
+/// 메모리에 올린 인덱스와 문서 테이블
+pub struct LoadedIndex {
+    searcher: IndexSearcher,
+    documents: DocumentTable,
+    /// 읽기 직전에 본 파일들의 상태
+    stamp: Stamp,
+}
+
+/// 인덱스 디렉터리의 파일들의 (수정 시각, 크기)
+/// 인덱스 파일, 문서 테이블, 삭제 표시 파일 중 하나라도 바뀌면 다시 읽는다.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp(Vec<Option<(SystemTime, u64)>>);
+
+impl Stamp {
+    fn of(index_path: &Path) -> Stamp {
+        Stamp(
+            [
+                index_path.to_path_buf(),
+                documents_path(index_path),
+                tombstones_path(index_path),
+            ]
+            .iter()
+            .map(|path| {
+                fs::metadata(path)
+                    .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                    .ok()
+            })
+            .collect(),
+        )
+    }
+}
+
+impl LoadedIndex {
+    /// 인덱스 파일과 그 옆의 문서 테이블을 읽는다. 문서 테이블이 없으면 빈 테이블을 쓴다.
+    pub fn open(index_path: &Path) -> Result<LoadedIndex, Error> {
+        // 읽는 중에 파일이 바뀌면 다음 확인 때 다시 읽도록 먼저 상태를 본다.
+        let stamp = Stamp::of(index_path);
+        let searcher = IndexSearcher::open(index_path)?;
+        let table_path = documents_path(index_path);
+        let documents = if table_path.exists() {
+            DocumentTable::open(table_path)?
+        } else {
+            DocumentTable::new()
+        };
+        Ok(LoadedIndex {
+            searcher,
+            documents,
+            stamp,
+        })
+    }
+
+    pub fn searcher(&self) -> &IndexSearcher {
+        &self.searcher
+    }
+
+    pub fn documents(&self) -> &DocumentTable {
+        &self.documents
+    }
+
+    /// 질의에 맞는 문서를 BM25 점수 순으로 정렬해서 한 페이지를 돌려준다.
+    /// 질의 문법이 틀리거나 `check_query`의 제한을 넘으면 `Error::InvalidQuery`를 돌려준다.
+    pub fn search(&self, params: &SearchParams) -> Result<SearchResponse, Error> {
+        check_query(&params.q)?;
+        let query = Query::parse(&params.q)?;
+        let matches = query.evaluate(&self.searcher)?;
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        let highlighter = Highlighter::default();
+        let terms = query.terms(&self.searcher)?;
+        let ranked = Bm25::default().rank(
+            &self.searcher,
+            &self.documents,
+            &terms,
+            &matches,
+            matches.len(),
+        )?;
+
+        let results = ranked
+            .into_iter()
+            .skip(params.offset)
+            .take(limit)
+            .map(|scored| {
+                let highlighted = term_positions(&self.searcher, &terms, scored.document_id)?;
+                let positions = matches
+                    .binary_search_by_key(&scored.document_id, |posting| posting.document_id)
+                    .map(|i| matches[i].positions.clone())
+                    .unwrap_or_default();
+                let document = self.documents.get(scored.document_id);
+                let snippets = document
+                    .and_then(|document| {
+                        highlighter
+                            .highlight_document(
+                                document,
+                                self.searcher.analyzer(),
+                                &highlighted,
+                                InvalidUtf8::Lossy,
+                            )
+                            .ok()
+                    })
+                    .unwrap_or_default();
+                Ok(SearchResult {
+                    document_id: scored.document_id,
+                    path: document.map(|document| document.path.clone()),
+                    record: document.and_then(|document| document.record),
+                    score: scored.score,
+                    positions,
+                    snippets,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(SearchResponse {
+            query: params.q.clone(),
+            total: matches.len(),
+            offset: params.offset,
+            limit,
+            results,
+        })
+    }
+}
+
+/// 해석하기 전에 질의의 길이와 괄호의 깊이를 확인한다.
+/// 누구나 보낼 수 있는 질의이므로 해석하느라 메모리나 스택을 많이 쓰기 전에 거절한다.
+pub fn check_query(q: &str) -> Result<(), Error> {
+    if q.len() > MAX_QUERY_LEN {
+        return Err(Error::InvalidQuery(format!(
+            "query is longer than {} bytes",
+            MAX_QUERY_LEN
+        )));
+    }
+    // 구절 안의 괄호는 단어의 일부이다.
+    let (_, _, deepest) = q.chars().fold(
+        (false, 0usize, 0usize),
+        |(in_phrase, depth, deepest), ch| match ch {
+            '"' => (!in_phrase, depth, deepest),
+            '(' if !in_phrase => (in_phrase, depth + 1, deepest.max(depth + 1)),
+            ')' if !in_phrase => (in_phrase, depth.saturating_sub(1), deepest),
+            _ => (in_phrase, depth, deepest),
+        },
+    );
+    if deepest > MAX_QUERY_DEPTH {
+        return Err(Error::InvalidQuery(format!(
+            "query is nested more than {} levels deep",
+            MAX_QUERY_DEPTH
+        )));
+    }
+    Ok(())
+}
+
+/// 검색하는 동안 다른 스레드가 새 인덱스로 바꿔 끼울 수 있는 인덱스
+/// 검색은 `current`로 받은 인덱스를 끝까지 쓰고, 바꿔 끼운 인덱스는 다음 검색부터 쓴다.
+pub struct ReloadableIndex {
+    index_path: PathBuf,
+    current: RwLock<Arc<LoadedIndex>>,
+}
+
+impl ReloadableIndex {
+    pub fn open<P: AsRef<Path>>(index_path: P) -> Result<ReloadableIndex, Error> {
+        let index_path = index_path.as_ref().to_path_buf();
+        let current = RwLock::new(Arc::new(LoadedIndex::open(&index_path)?));
+        Ok(ReloadableIndex {
+            index_path,
+            current,
+        })
+    }
+
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    /// 지금 쓰고 있는 인덱스
+    pub fn current(&self) -> Arc<LoadedIndex> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// 파일이 바뀌었으면 다시 읽어서 바꿔 끼우고 true를 돌려준다.
+    /// 다시 읽지 못하면 오류를 돌려주고 지금 인덱스를 계속 쓴다. 다음에 부르면 다시 시도한다.
+    pub fn reload_if_changed(&self) -> Result<bool, Error> {
+        if self.current().stamp == Stamp::of(&self.index_path) {
+            return Ok(false);
+        }
+        let loaded = LoadedIndex::open(&self.index_path)?;
+        *self.current.write().unwrap() = Arc::new(loaded);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::Pipeline,
+        test_util::{build_index, TestDir},
+    };
+
+    #[test]
+    fn test_search_rejects_deep_queries() {
+        let dir = TestDir::new("server");
+        let index_path = build_index(&dir, &Pipeline::default(), &[("a.txt", "gap buffer")]);
+        let index = LoadedIndex::open(&index_path).unwrap();
+        let search = |q: String| {
+            index.search(&SearchParams {
+                q,
+                offset: 0,
+                limit: None,
+            })
+        };
+
+        let found = search("(gap) AND \"(buffer\"".to_string()).unwrap();
+        assert_eq!(found.total, 1);
+        assert_eq!(found.results[0].path, Some(dir.join("a.txt")));
+
+        let nested = format!("{}gap{}", "(".repeat(8000), ")".repeat(8000));
+        assert!(matches!(search(nested), Err(Error::InvalidQuery(_))));
+        let deep = format!(
+            "{}gap{}",
+            "(".repeat(MAX_QUERY_DEPTH + 1),
+            ")".repeat(MAX_QUERY_DEPTH + 1)
+        );
+        assert!(matches!(search(deep), Err(Error::InvalidQuery(_))));
+        let long = vec!["gap"; MAX_QUERY_LEN].join(" ");
+        assert!(matches!(search(long), Err(Error::InvalidQuery(_))));
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use byteorder::{ByteOrder, LittleEndian};
+use fst::{
+    automaton::{Levenshtein, Str},
+    Automaton, IntoStreamer, Map, MapBuilder, Streamer,
+};
+
+use crate::{
+    error::{Error, PathContext},
+    header::FormatError,
+};
+
+/// 단어 사전 파일의 확장자. index.dat의 단어 사전은 index.fst이다.
+pub const TERMS_EXTENSION: &str = "fst";
+
+/// 단어에서 인덱스 테이블의 순번을 찾는 FST 사전
+/// 단어를 하나씩 찾는 것 말고도 접두어, 와일드카드, 편집 거리로 단어를 찾을 수 있다.
+pub struct TermDictionary {
+    map: Map<Vec<u8>>,
+}
+
+/// 파일 구조
+/// [contents_crc: 4B][fst]
+/// contents_crc는 사전을 만든 인덱스 파일의 테이블 CRC-32이다. 인덱스와 맞지 않는 사전은 쓰지 않는다.
+impl TermDictionary {
+    /// 정렬된 단어 목록으로 사전을 만든다. 단어의 값은 목록에서의 순번이다.
+    /// 정렬되어 있지 않거나 같은 단어가 있으면 `FormatError::UnsortedTerms`를 돌려준다.
+    pub fn build<'a, I: IntoIterator<Item = &'a str>>(terms: I) -> Result<Self, Error> {
+        let mut builder = TermDictionaryBuilder::new();
+        terms
+            .into_iter()
+            .try_for_each(|term| builder.insert(term))?;
+        builder.finish()
+    }
+
+    /// 사전 파일을 읽는다. 파일이 없거나 contents_crc가 다르거나 FST가 깨졌으면 None을 돌려준다.
+    pub fn open<P: AsRef<Path>>(filename: P, contents_crc: u32) -> Result<Option<Self>, Error> {
+        let bytes = match fs::read(filename.as_ref()) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_path(filename),
+        };
+        if bytes.len() < 4 || LittleEndian::read_u32(&bytes) != contents_crc {
+            return Ok(None);
+        }
+        Ok(Map::new(bytes[4..].to_vec())
+            .ok()
+            .map(|map| TermDictionary { map }))
+    }
+
+    /// 사전을 파일로 쓴다.
+    pub fn write<P: AsRef<Path>>(&self, filename: P, contents_crc: u32) -> Result<(), Error> {
+        let fst = self.map.as_fst().as_bytes();
+        let mut bytes = Vec::with_capacity(4 + fst.len());
+        bytes.extend(contents_crc.to_le_bytes());
+        bytes.extend(fst);
+        fs::write(filename.as_ref(), bytes).with_path(filename)
+    }
+
+    /// 사전에 있는 단어의 수
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// 단어의 순번
+    pub fn get(&self, term: &str) -> Option<u64> {
+        self.map.get(term)
+    }
+
+    /// automaton에 맞는 단어의 순번을 단어 순으로 돌려준다.
+    pub fn search<A: Automaton>(&self, automaton: A) -> Vec<u64> {
+        let mut stream = self.map.search(automaton).into_stream();
+        let mut found = Vec::new();
+        while let Some((_, i)) = stream.next() {
+            found.push(i);
+        }
+        found
+    }
+
+    /// prefix로 시작하는 단어
+    pub fn prefix(&self, prefix: &str) -> Vec<u64> {
+        self.search(Str::new(prefix).starts_with())
+    }
+
+    /// 와일드카드 패턴에 맞는 단어. `*`는 0글자 이상, `?`는 한 글자에 맞는다.
+    pub fn wildcard(&self, pattern: &str) -> Vec<u64> {
+        self.search(Wildcard::new(pattern))
+    }
+
+    /// term과의 편집 거리가 distance 이하인 단어
+    /// 거리가 크면 오토마톤이 너무 커져서 `Error::InvalidQuery`를 돌려준다.
+    pub fn fuzzy(&self, term: &str, distance: u32) -> Result<Vec<u64>, Error> {
+        let automaton =
+            Levenshtein::new(term, distance).map_err(|e| Error::InvalidQuery(e.to_string()))?;
+        Ok(self.search(automaton))
+    }
+}
+
+/// 단어를 정렬된 순서로 하나씩 넣어서 사전을 만든다. 인덱스 파일의 테이블을 쓰면서 함께 만든다.
+pub struct TermDictionaryBuilder {
+    builder: MapBuilder<Vec<u8>>,
+    /// 다음 단어의 순번
+    next: u64,
+}
+
+impl TermDictionaryBuilder {
+    pub fn new() -> Self {
+        TermDictionaryBuilder {
+            builder: MapBuilder::memory(),
+            next: 0,
+        }
+    }
+
+    /// 단어를 다음 순번으로 넣는다.
+    /// 앞 단어보다 크지 않으면 `FormatError::UnsortedTerms`를 돌려준다.
+    pub fn insert(&mut self, term: &str) -> Result<(), Error> {
+        self.builder
+            .insert(term, self.next)
+            .map_err(|_| FormatError::UnsortedTerms)?;
+        self.next += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<TermDictionary, Error> {
+        let bytes = self.builder.into_inner().map_err(io::Error::other)?;
+        let map = Map::new(bytes).map_err(|_| FormatError::UnsortedTerms)?;
+        Ok(TermDictionary { map })
+    }
+}
+
+impl Default for TermDictionaryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 인덱스 파일의 단어 사전 경로
+pub fn terms_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension(TERMS_EXTENSION)
+}
+
+/// 와일드카드 패턴의 한 부분
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Byte(u8),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyString,
+}
+
+/// 와일드카드 패턴 오토마톤
+/// 상태는 (다음에 맞출 패턴의 위치, 그 전에 받아야 할 UTF-8 연속 바이트 수)의 집합이다.
+struct Wildcard {
+    items: Vec<Item>,
+}
+
+impl Wildcard {
+    fn new(pattern: &str) -> Self {
+        let items = pattern
+            .chars()
+            .flat_map(|ch| match ch {
+                '*' => vec![Item::AnyString],
+                '?' => vec![Item::AnyChar],
+                ch => ch.to_string().bytes().map(Item::Byte).collect(),
+            })
+            .collect();
+        Wildcard { items }
+    }
+
+    /// `*`는 아무것도 받지 않고 건너뛸 수도 있다.
+    fn close(&self, mut states: Vec<(usize, u8)>) -> Vec<(usize, u8)> {
+        let mut i = 0;
+        while i < states.len() {
+            if let (at, 0) = states[i] {
+                if self.items.get(at) == Some(&Item::AnyString) && !states.contains(&(at + 1, 0)) {
+                    states.push((at + 1, 0));
+                }
+            }
+            i += 1;
+        }
+        states.sort_unstable();
+        states.dedup();
+        states
+    }
+}
+
+/// UTF-8 첫 바이트 뒤에 오는 연속 바이트 수
+fn continuation_len(byte: u8) -> u8 {
+    match byte {
+        0xf0.. => 3,
+        0xe0.. => 2,
+        0xc0.. => 1,
+        _ => 0,
+    }
+}
+
+impl Automaton for Wildcard {
+    type State = Vec<(usize, u8)>;
+
+    fn start(&self) -> Self::State {
+        self.close(vec![(0, 0)])
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state.contains(&(self.items.len(), 0))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        !state.is_empty()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let next = state
+            .iter()
+            .filter_map(|&(at, pending)| {
+                if pending > 0 {
+                    return Some((at, pending - 1));
+                }
+                match self.items.get(at)? {
+                    Item::Byte(b) if *b == byte => Some((at + 1, 0)),
+                    Item::Byte(_) => None,
+                    Item::AnyChar => Some((at + 1, continuation_len(byte))),
+                    Item::AnyString => Some((at, continuation_len(byte))),
+                }
+            })
+            .collect();
+        self.close(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_term_dictionary() {
+        let terms = [
+            "buffer", "buffers", "gap", "gaps", "gape", "gzip", "한글", "한국",
+        ];
+        let mut sorted = terms.to_vec();
+        sorted.sort_unstable();
+        let dictionary = TermDictionary::build(sorted.iter().copied()).unwrap();
+        let words = |found: Vec<u64>| {
+            found
+                .into_iter()
+                .map(|i| sorted[i as usize])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            dictionary.get("gaps").map(|i| sorted[i as usize]),
+            Some("gaps")
+        );
+        assert_eq!(dictionary.get("ga"), None);
+        assert_eq!(words(dictionary.prefix("gap")), ["gap", "gape", "gaps"]);
+        assert_eq!(words(dictionary.wildcard("g*p")), ["gap", "gzip"]);
+        assert_eq!(words(dictionary.wildcard("g?p?")), ["gape", "gaps"]);
+        assert_eq!(words(dictionary.wildcard("*er*")), ["buffer", "buffers"]);
+        assert_eq!(words(dictionary.wildcard("한?")), ["한국", "한글"]);
+        assert_eq!(words(dictionary.fuzzy("bufer", 1).unwrap()), ["buffer"]);
+        assert_eq!(
+            words(dictionary.fuzzy("gap", 1).unwrap()),
+            ["gap", "gape", "gaps"]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! 테스트에서 함께 쓰는 임시 디렉터리와 인덱스
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use crate::{
+    analysis::Pipeline,
+    documents::{documents_path, Document, DocumentTable},
+    index::InMemoryIndex,
+    merge::FileMerge,
+    progress::NoProgress,
+    tmp::TmpDir,
+    write::write_index_to_tmp_file,
+};
+
+/// 테스트마다 따로 쓰는 임시 디렉터리. drop하면 지운다.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// 같은 프로세스의 다른 테스트와 겹치지 않도록 테스트마다 다른 name을 쓴다.
+    pub fn new(name: &str) -> TestDir {
+        let path = env::temp_dir().join(format!("fingertips-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// (파일 이름, 본문) 문서들을 dir에 쓰고 색인해서 dir/index.dat와 문서 테이블을 만든다.
+/// 문서 번호는 0부터 순서대로이다. 문서마다 임시 파일을 따로 써서 병합하므로 병합도 거친다.
+pub fn build_index(dir: &TestDir, analyzer: &Pipeline, documents: &[(&str, &str)]) -> PathBuf {
+    let index_path = dir.join("index.dat");
+    let mut tmp_dir = TmpDir::new(dir.path());
+    let mut merge = FileMerge::new(&index_path, tmp_dir.clone(), 2, Arc::new(NoProgress));
+    let mut table = DocumentTable::new();
+    documents.iter().enumerate().for_each(|(id, (name, text))| {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        let index = InMemoryIndex::from_single_document(id, text.to_string(), analyzer);
+        table.push(Document {
+            id: id as u32,
+            path,
+            record: None,
+            offset: 0,
+            len: text.len() as u64,
+            word_count: index.word_count as u64,
+            mtime: 0,
+        });
+        let file =
+            write_index_to_tmp_file(index, &mut tmp_dir, analyzer.spec(), &NoProgress).unwrap();
+        merge.add_file(file).unwrap();
+    });
+    merge.finish().unwrap();
+    table.write(documents_path(&index_path)).unwrap();
+    index_path
+}
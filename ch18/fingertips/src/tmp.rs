@@ -2,43 +2,71 @@ use std::{
     fs::{self, File},
     io::{self, BufWriter},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use crate::error::{Error, PathContext};
+
+/// 임시 파일을 만드는 디렉터리
+/// 복제한 TmpDir은 번호와 만든 파일 목록을 함께 쓰므로 여러 스레드에 나눠줄 수 있다.
+#[derive(Clone)]
 pub struct TmpDir {
     dir: PathBuf,
-    n: usize,
+    n: Arc<AtomicUsize>,
+    /// 지금까지 만든 파일. 실패했을 때 `cleanup`으로 지운다.
+    created: Arc<Mutex<Vec<PathBuf>>>,
 }
 
 impl TmpDir {
     pub fn new<P: AsRef<Path>>(dir: P) -> Self {
         TmpDir {
             dir: dir.as_ref().to_owned(),
-            n: 1,
+            n: Arc::new(AtomicUsize::new(1)),
+            created: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    pub fn create(&mut self) -> Result<(PathBuf, BufWriter<File>), io::Error> {
+    pub fn create(&mut self) -> Result<(PathBuf, BufWriter<File>), Error> {
         let mut r#try = 1;
         loop {
-            let filename = self
-                .dir
-                .join(PathBuf::from(format!("tmp{:08x}.dat", self.n)));
-            self.n += 1;
+            let filename = self.dir.join(PathBuf::from(format!(
+                "tmp{:08x}.dat",
+                self.n.fetch_add(1, Ordering::Relaxed)
+            )));
             match fs::OpenOptions::new()
                 .write(true)
                 .create_new(true)
                 .open(&filename)
             {
-                Ok(f) => return Ok((filename, BufWriter::new(f))),
+                Ok(f) => {
+                    self.created.lock().unwrap().push(filename.clone());
+                    return Ok((filename, BufWriter::new(f)));
+                }
                 Err(exc) => {
                     if r#try < 999 && exc.kind() == io::ErrorKind::AlreadyExists {
                         // 계속 실행한다.
                     } else {
-                        return Err(exc);
+                        return Err(Error::from(exc).with_path(filename));
                     }
                 }
             }
             r#try += 1;
         }
     }
+
+    /// 만든 임시 파일 중에서 아직 남아 있는 파일을 지운다.
+    /// 병합하면서 지웠거나 인덱스로 옮긴 파일은 건너뛴다.
+    pub fn cleanup(&self) -> Result<(), Error> {
+        self.created
+            .lock()
+            .unwrap()
+            .drain(..)
+            .try_for_each(|filename| match fs::remove_file(&filename) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e).with_path(filename),
+                _ => Ok(()),
+            })
+    }
 }
@@ -0,0 +1,159 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::{Error, PathContext};
+
+/// 삭제 표시 파일의 확장자. index.dat의 삭제 표시 파일은 index.deleted이다.
+pub const TOMBSTONES_EXTENSION: &str = "deleted";
+
+/// 인덱스 파일의 삭제 표시 파일 경로
+pub fn tombstones_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension(TOMBSTONES_EXTENSION)
+}
+
+/// 지운 경로 파일의 확장자. index.dat의 지운 경로 파일은 index.excluded이다.
+pub const EXCLUDED_EXTENSION: &str = "excluded";
+
+/// 인덱스 파일의 지운 경로 파일 경로
+pub fn excluded_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension(EXCLUDED_EXTENSION)
+}
+
+/// 지운 문서 번호의 비트맵
+/// 인덱스를 읽을 때는 여기 있는 문서를 건너뛰고,
+/// 병합하면서 인덱스 데이터를 다시 쓸 때 실제로 지운다.
+#[derive(Debug, Default, Clone)]
+pub struct Tombstones {
+    bits: Vec<u64>,
+}
+
+/// 파일 구조
+/// [bits: 8B](n)
+impl Tombstones {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 파일에서 비트맵을 읽는다. 파일이 없으면 빈 비트맵을 돌려준다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<Tombstones, Error> {
+        let filename = filename.as_ref();
+        let f = match File::open(filename) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Tombstones::new()),
+            Err(e) => return Err(Error::from(e).with_path(filename)),
+        };
+        let len = f.metadata().with_path(filename)?.len() / 8;
+        let mut f = BufReader::new(f);
+        let bits = (0..len)
+            .map(|_| f.read_u64::<LittleEndian>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_path(filename)?;
+        Ok(Tombstones { bits })
+    }
+
+    /// 비트맵을 파일에 쓴다.
+    pub fn write<P: AsRef<Path>>(&self, filename: P) -> Result<(), Error> {
+        let filename = filename.as_ref();
+        let mut f = BufWriter::new(File::create(filename).with_path(filename)?);
+        self.bits
+            .iter()
+            .try_for_each(|&word| f.write_u64::<LittleEndian>(word))
+            .and_then(|()| f.flush())
+            .with_path(filename)
+    }
+
+    /// 문서를 지운 것으로 표시한다. 새로 표시했으면 true를 반환한다.
+    pub fn insert(&mut self, document_id: u32) -> bool {
+        let (word, bit) = (document_id as usize / 64, document_id % 64);
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        let inserted = self.bits[word] & (1 << bit) == 0;
+        self.bits[word] |= 1 << bit;
+        inserted
+    }
+
+    /// 지운 문서일 때 true를 반환한다.
+    pub fn contains(&self, document_id: u32) -> bool {
+        let (word, bit) = (document_id as usize / 64, document_id % 64);
+        self.bits
+            .get(word)
+            .is_some_and(|&word| word & (1 << bit) != 0)
+    }
+
+    /// 지운 문서의 수
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// 지운 문서 번호를 작은 순으로 돌려준다.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.bits.len() as u32 * 64).filter(|&id| self.contains(id))
+    }
+}
+
+/// delete로 지운 파일이나 디렉터리의 경로 목록
+/// 디렉터리를 다시 훑으면 지운 파일도 다시 찾으므로, --append는 이 경로 아래의 파일을 색인하지 않는다.
+#[derive(Debug, Default, Clone)]
+pub struct ExcludedPaths {
+    paths: Vec<PathBuf>,
+}
+
+/// 파일 구조
+/// 한 줄에 경로 하나
+impl ExcludedPaths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 파일에서 목록을 읽는다. 파일이 없으면 빈 목록을 돌려준다.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<ExcludedPaths, Error> {
+        let filename = filename.as_ref();
+        let text = match fs::read_to_string(filename) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ExcludedPaths::new()),
+            Err(e) => return Err(Error::from(e).with_path(filename)),
+        };
+        Ok(ExcludedPaths {
+            paths: text.lines().map(PathBuf::from).collect(),
+        })
+    }
+
+    /// 목록을 파일에 쓴다.
+    pub fn write<P: AsRef<Path>>(&self, filename: P) -> Result<(), Error> {
+        let filename = filename.as_ref();
+        let mut f = BufWriter::new(File::create(filename).with_path(filename)?);
+        self.paths
+            .iter()
+            .try_for_each(|path| writeln!(f, "{}", path.to_string_lossy()))
+            .and_then(|()| f.flush())
+            .with_path(filename)
+    }
+
+    /// 경로를 목록에 넣는다. 이미 있으면 false를 돌려준다.
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        let path = path.as_ref();
+        if self.paths.iter().any(|excluded| excluded == path) {
+            return false;
+        }
+        self.paths.push(path.to_path_buf());
+        true
+    }
+
+    /// path가 목록의 경로이거나 그 아래에 있는지
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.iter().any(|excluded| path.starts_with(excluded))
+    }
+}
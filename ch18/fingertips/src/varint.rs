@@ -0,0 +1,90 @@
+use crate::{error::Error, header::FormatError};
+
+/// 정수를 가변 길이로 쓴다.
+/// 한 바이트에 7비트씩 작은 자리부터 쓰고, 뒤에 바이트가 더 있으면 최상위 비트를 켠다.
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// 가변 길이 정수를 읽고 buf를 읽은 만큼 앞으로 옮긴다.
+pub fn read_varint(buf: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or(FormatError::BadPostings("unexpected end of varint"))?;
+        *buf = rest;
+        if shift > 63 {
+            return Err(FormatError::BadPostings("varint is too long").into());
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// u32 범위의 가변 길이 정수를 읽는다.
+pub fn read_varint_u32(buf: &mut &[u8]) -> Result<u32, Error> {
+    u32::try_from(read_varint(buf)?)
+        .map_err(|_| FormatError::BadPostings("varint is out of range").into())
+}
+
+/// 가변 길이 정수 n개를 디코딩하지 않고 건너뛴다.
+pub fn skip_varints(buf: &mut &[u8], n: usize) -> Result<(), Error> {
+    let mut remaining = n;
+    let mut i = 0;
+    while remaining > 0 {
+        let byte = *buf
+            .get(i)
+            .ok_or(FormatError::BadPostings("unexpected end of varint"))?;
+        if byte & 0x80 == 0 {
+            remaining -= 1;
+        }
+        i += 1;
+    }
+    *buf = &buf[i..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_round_trip() {
+        let values = [
+            0,
+            1,
+            127,
+            128,
+            300,
+            16_383,
+            16_384,
+            u32::MAX as u64,
+            u64::MAX,
+        ];
+        let mut buf = Vec::new();
+        values
+            .iter()
+            .for_each(|&value| write_varint(&mut buf, value));
+        assert_eq!(&buf[..4], [0, 1, 127, 0x80]);
+
+        let mut rest = buf.as_slice();
+        values
+            .iter()
+            .for_each(|&value| assert_eq!(read_varint(&mut rest).unwrap(), value));
+        assert!(rest.is_empty());
+
+        let mut rest = buf.as_slice();
+        skip_varints(&mut rest, 4).unwrap();
+        assert_eq!(read_varint(&mut rest).unwrap(), 300);
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+use crate::{
+    error::{Error, PathContext},
+    extract::SUPPORTED_EXTENSIONS,
+    parse_args::Args,
+    progress::{Event, Progress},
+};
+
+/// 디렉터리에서 --include를 지정하지 않았을 때 색인할 파일의 glob
+/// `extract::SUPPORTED_EXTENSIONS`의 파일과 그 .gz 파일이다.
+pub fn default_includes() -> Vec<String> {
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .flat_map(|ext| [format!("*.{}", ext), format!("*.{}.gz", ext)])
+        .collect()
+}
+
+/// .gitignore와 같은 형식으로 색인하지 않을 파일을 적는 파일
+pub const IGNORE_FILENAME: &str = ".fingertipsignore";
+
+/// 색인할 파일 목록을 작성한다.
+/// 파일은 그대로 넣고, 디렉터리는 하위 디렉터리까지 훑어서 --include에 맞고 --exclude에 맞지 않는 파일만 넣는다.
+/// 디렉터리 안의 .gitignore, .fingertipsignore와 숨은 파일은 --no-ignore를 주지 않으면 건너뛴다.
+/// 심볼릭 링크는 --follow-symlinks를 주었을 때만 따라간다.
+/// --max-file-size보다 큰 파일은 건너뛴다. 지정한 파일을 건너뛰면 progress에 `Event::DocumentSkipped`를 보낸다.
+/// 깨진 링크, 링크의 순환, 읽을 수 없는 디렉터리처럼 훑다가 만난 오류도 progress로 보내고 계속 훑는다.
+pub fn expand_filename_arguments(
+    args: &Args,
+    progress: &dyn Progress,
+) -> Result<Vec<PathBuf>, Error> {
+    args.filename()
+        .iter()
+        .map(PathBuf::from)
+        .try_fold(vec![], |mut filenames, path| {
+            if path.metadata().with_path(&path)?.is_dir() {
+                filenames.extend(walk_dir(&path, args, progress)?);
+            } else if is_small_enough(&path, args, progress)? {
+                filenames.push(path);
+            }
+
+            Ok(filenames)
+        })
+}
+
+/// 디렉터리를 훑어서 파일 이름 순으로 돌려준다.
+/// 읽을 수 없는 항목은 progress에 `Event::DocumentSkipped`를 보내고 건너뛴다.
+fn walk_dir(dir: &Path, args: &Args, progress: &dyn Progress) -> Result<Vec<PathBuf>, Error> {
+    // --include를 walker의 override에 넣으면 맞는 파일은 숨은 파일이나 ignore 파일에 있어도 나오므로
+    // walker에는 --exclude만 넣고 --include는 훑은 파일에 따로 맞춰 본다.
+    let mut includes = OverrideBuilder::new(dir);
+    match args.include() {
+        [] => default_includes(),
+        globs => globs.to_vec(),
+    }
+    .iter()
+    .try_for_each(|glob| includes.add(glob).map(|_| ()))
+    .map_err(to_error)?;
+    let includes = includes.build().map_err(to_error)?;
+
+    let mut overrides = OverrideBuilder::new(dir);
+    args.exclude()
+        .iter()
+        .try_for_each(|glob| overrides.add(&format!("!{}", glob)).map(|_| ()))
+        .map_err(to_error)?;
+
+    let mut walker = WalkBuilder::new(dir);
+    walker
+        .standard_filters(!args.is_no_ignore())
+        .require_git(false)
+        .follow_links(args.is_follow_symlinks())
+        .max_filesize(args.max_file_size())
+        .overrides(overrides.build().map_err(to_error)?)
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if !args.is_no_ignore() {
+        walker.add_custom_ignore_filename(IGNORE_FILENAME);
+    }
+
+    Ok(walker
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                progress.event(Event::DocumentSkipped {
+                    path: error_path(&e).unwrap_or(dir).to_path_buf(),
+                    reason: e.to_string(),
+                });
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter(|entry| includes.matched(entry.path(), false).is_whitelist())
+        .map(|entry| entry.into_path())
+        .collect())
+}
+
+/// 오류가 난 항목의 경로. 링크의 순환은 순환을 만든 링크이다.
+fn error_path(e: &ignore::Error) -> Option<&Path> {
+    match e {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
+}
+
+/// 파일이 --max-file-size보다 크지 않으면 true를 반환한다.
+fn is_small_enough(path: &Path, args: &Args, progress: &dyn Progress) -> Result<bool, Error> {
+    let len = path.metadata().with_path(path)?.len();
+    match args.max_file_size() {
+        Some(max) if len > max => {
+            progress.event(Event::DocumentSkipped {
+                path: path.to_path_buf(),
+                reason: format!("{} bytes is larger than --max-file-size", len),
+            });
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// 경로가 담긴 메시지는 그대로 두고 원래 오류의 종류를 쓴다.
+/// 잘못된 glob처럼 입출력 오류가 아니면 `Error::InvalidArgument`이다.
+fn to_error(e: ignore::Error) -> Error {
+    match e.io_error().map(|inner| inner.kind()) {
+        Some(kind) => io::Error::new(kind, e).into(),
+        None => Error::InvalidArgument(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, sync::Mutex};
+
+    use super::*;
+    use crate::{
+        parse_args::{parse_args_from, Command},
+        test_util::TestDir,
+    };
+
+    /// dir/corpus를 훑어서 찾은 파일과 건너뛴 항목을 corpus 안의 경로로 돌려준다.
+    fn walk(dir: &TestDir, extra: &[&str]) -> (Vec<String>, Vec<String>) {
+        let corpus = dir.join("corpus");
+        let args = ["fingertips"]
+            .into_iter()
+            .chain(extra.iter().copied())
+            .chain([corpus.to_str().unwrap()]);
+        let args = match parse_args_from(args) {
+            Command::Index(args) => args,
+            command => panic!("expected index command, got {:?}", command),
+        };
+        let skipped = Mutex::new(Vec::new());
+        let record = |event| {
+            if let Event::DocumentSkipped { path, .. } = event {
+                skipped.lock().unwrap().push(path);
+            }
+        };
+        let found = expand_filename_arguments(&args, &record).unwrap();
+        let relative = |paths: Vec<PathBuf>| {
+            paths
+                .iter()
+                .map(|path| path.strip_prefix(&corpus).unwrap().display().to_string())
+                .collect()
+        };
+        (relative(found), relative(skipped.into_inner().unwrap()))
+    }
+
+    fn write_files(dir: &TestDir, files: &[(&str, &str)]) {
+        files.iter().for_each(|(name, text)| {
+            let path = dir.join("corpus").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_ignore_files_and_hidden_files() {
+        let dir = TestDir::new("walk-ignore");
+        write_files(
+            &dir,
+            &[
+                ("a.txt", "a"),
+                ("b.md", "b"),
+                ("c.rs", "c"),
+                (".hidden.txt", "h"),
+                ("logs/d.txt", "d"),
+                ("sub/e.txt", "e"),
+                ("sub/f.txt", "f"),
+                (".gitignore", "logs/\n"),
+                ("sub/.fingertipsignore", "f.txt\n"),
+            ],
+        );
+
+        let (found, skipped) = walk(&dir, &[]);
+        assert_eq!(found, ["a.txt", "b.md", "sub/e.txt"]);
+        assert!(skipped.is_empty());
+
+        let (found, _) = walk(&dir, &["--no-ignore"]);
+        assert_eq!(
+            found,
+            [
+                ".hidden.txt",
+                "a.txt",
+                "b.md",
+                "logs/d.txt",
+                "sub/e.txt",
+                "sub/f.txt"
+            ]
+        );
+
+        let (found, _) = walk(&dir, &["--include", "*.rs", "--exclude", "sub"]);
+        assert_eq!(found, ["c.rs"]);
+    }
+
+    #[test]
+    fn test_symlinks() {
+        let dir = TestDir::new("walk-symlinks");
+        write_files(&dir, &[("a.txt", "a"), ("real/b.txt", "b")]);
+        let corpus = dir.join("corpus");
+        symlink(corpus.join("real"), corpus.join("linked")).unwrap();
+        symlink(corpus.join("missing.txt"), corpus.join("broken.txt")).unwrap();
+        symlink(&corpus, corpus.join("real").join("loop")).unwrap();
+
+        let (found, skipped) = walk(&dir, &[]);
+        assert_eq!(found, ["a.txt", "real/b.txt"]);
+        assert!(skipped.is_empty());
+
+        // 깨진 링크와 순환은 건너뛰고 나머지는 계속 훑는다.
+        let (found, skipped) = walk(&dir, &["--follow-symlinks"]);
+        assert_eq!(found, ["a.txt", "linked/b.txt", "real/b.txt"]);
+        assert_eq!(skipped.len(), 3);
+        assert!(skipped.contains(&"broken.txt".to_string()));
+        assert!(skipped.contains(&"real/loop".to_string()));
+    }
+}
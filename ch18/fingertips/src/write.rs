@@ -6,31 +6,53 @@ use std::{
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
-use crate::{index::InMemoryIndex, tmp::TmpDir};
+use crate::{
+    error::{Error, PathContext},
+    header::{Header, PATCH_OFFSET},
+    index::{split_hits, InMemoryIndex, PostingListBuilder},
+    progress::{Event, Progress},
+    tmp::TmpDir,
+};
 
 // 인덱스를 파일에 저장하기 위한 Writer
 pub struct IndexFileWriter {
     offset: u64,
     writer: BufWriter<File>,
     content_buf: Vec<u8>,
+    /// 테이블의 엔트리 수
+    term_count: u64,
+    /// 지금까지 쓴 인덱스 데이터의 CRC-32
+    main_crc: crc32fast::Hasher,
+    /// 헤더에 기록할 분석기 설정
+    analyzer: String,
 }
 
 /// 파일 구조
-/// [offset: 8B][hits(n):8 * n Byte](offset->|)[[hits_offset: 8B][hits_byte_len: 8B][hits_len: 4B][term_len: 4B][term: term_len B]...]
+/// 헤더의 구조는 `header::Header`, Posting 목록의 구조는 `index::PostingListBuilder` 참고
+/// [header: Header::size() B][postings(n)](offset->|)[[hits_offset: 8B][hits_byte_len: 8B][hits_len: 4B][term_len: 4B][term: term_len B]...]
 impl IndexFileWriter {
-    pub fn new(mut f: BufWriter<File>) -> Result<IndexFileWriter, io::Error> {
-        const HEADER_SIZE: u64 = 8;
-        f.write_u64::<LittleEndian>(0)?;
+    /// analyzer는 문서를 색인할 때 쓴 분석기의 설정이다.
+    pub fn new(mut f: BufWriter<File>, analyzer: &str) -> Result<IndexFileWriter, Error> {
+        Header::write_placeholder(&mut f, analyzer)?;
         Ok(IndexFileWriter {
-            offset: HEADER_SIZE,
+            offset: Header::size_with(analyzer),
             writer: f,
             content_buf: Vec::new(),
+            term_count: 0,
+            main_crc: crc32fast::Hasher::new(),
+            analyzer: analyzer.to_string(),
         })
     }
 
+    /// 다음에 쓸 인덱스 데이터의 위치
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
     /// writer에 buf의 내용을 쓴다.
-    pub fn write_main(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+    pub fn write_main(&mut self, buf: &[u8]) -> Result<(), Error> {
         self.writer.write_all(buf)?;
+        self.main_crc.update(buf);
         self.offset += buf.len() as u64;
 
         Ok(())
@@ -46,46 +68,69 @@ impl IndexFileWriter {
             .write_u32::<LittleEndian>(bytes.len() as u32)
             .unwrap();
         self.content_buf.extend(bytes);
+        self.term_count += 1;
     }
 
-    pub fn finish(&mut self) -> Result<(), io::Error> {
+    /// 테이블을 쓰고 헤더를 채운다. 파일 전체의 바이트 수를 돌려준다.
+    pub fn finish(&mut self) -> Result<u64, Error> {
         let content_start = self.offset;
         self.writer.write_all(&self.content_buf)?;
-        println!(
-            "{} bytes main, {} bytes total",
-            content_start,
-            content_start + self.content_buf.len() as u64
-        );
-        self.writer.seek(io::SeekFrom::Start(0))?;
-        self.writer.write_u64::<LittleEndian>(content_start)?;
-
-        Ok(())
+        let header = Header {
+            term_count: self.term_count,
+            contents_offset: content_start,
+            main_crc: self.main_crc.clone().finalize(),
+            contents_crc: crc32fast::hash(&self.content_buf),
+            analyzer: self.analyzer.clone(),
+        };
+        self.writer.seek(io::SeekFrom::Start(PATCH_OFFSET))?;
+        header.write_patch(&mut self.writer)?;
+        self.writer.flush()?;
+        Ok(content_start + self.content_buf.len() as u64)
     }
 }
 
+/// 메모리의 인덱스를 임시 파일로 쓴다. analyzer는 색인할 때 쓴 분석기의 설정이다.
+/// 다 쓰면 progress에 `Event::TmpFileWritten`을 보낸다.
 pub fn write_index_to_tmp_file(
     index: InMemoryIndex,
     tmp_dir: &mut TmpDir,
-) -> Result<PathBuf, io::Error> {
+    analyzer: &str,
+    progress: &dyn Progress,
+) -> Result<PathBuf, Error> {
     let (filename, f) = tmp_dir.create()?;
-    let mut writer = IndexFileWriter::new(f)?;
+    let mut writer = IndexFileWriter::new(f, analyzer).with_path(&filename)?;
 
     let mut index_as_vec = index.map.into_iter().collect::<Vec<_>>();
     index_as_vec.sort_by(|(a, _), (b, _)| a.cmp(b));
 
     index_as_vec.into_iter().try_for_each(|(term, hits)| {
-        let df = hits.len() as u32;
+        let mut raw_hits = hits
+            .iter()
+            .map(|hit| split_hits(hit))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        raw_hits.sort_by_key(|hit| (hit.document_id, hit.field));
+        let mut postings = PostingListBuilder::new();
+        raw_hits
+            .into_iter()
+            .try_for_each(|hit| postings.push(hit))?;
+
         let start = writer.offset;
-        hits.into_iter()
-            .try_for_each(|buffer| writer.write_main(&buffer))?;
+        let df = postings.df();
+        writer
+            .write_main(&postings.into_bytes())
+            .with_path(&filename)?;
         let stop = writer.offset;
         writer.write_content_entry(term, df, start, stop - start);
 
-        <Result<(), io::Error>>::Ok(())
+        <Result<(), Error>>::Ok(())
     })?;
 
-    writer.finish()?;
-    println!("wrote file {:?}", filename);
+    let bytes = writer.finish().with_path(&filename)?;
+    progress.event(Event::TmpFileWritten {
+        path: filename.clone(),
+        bytes,
+    });
 
     Ok(filename)
 }